@@ -0,0 +1,76 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// Retry delays that double after each failure up to a cap. Each delay is
+/// picked at random from the upper half of its range, so machines that lost
+/// the network together don't all retry in the same second.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    failures: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            failures: 0,
+        }
+    }
+
+    /// Count another failure and return how long to wait before retrying
+    pub fn next_delay(&mut self) -> Duration {
+        let ceiling = self
+            .initial
+            .checked_mul(1 << self.failures.min(20))
+            .map_or(self.max, |delay| delay.min(self.max));
+        self.failures = self.failures.saturating_add(1);
+        ceiling / 2 + ceiling.mul_f64(random_fraction() / 2.0)
+    }
+
+    /// Start over from the initial delay after a success
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+}
+
+/// A number in `[0, 1)`. The standard library seeds each `RandomState`
+/// with fresh random keys, which is plenty for jitter.
+fn random_fraction() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delays_double_up_to_the_cap() {
+        let mut backoff = Backoff::new(Duration::from_secs(30), Duration::from_secs(3600));
+        for ceiling in [30, 60, 120, 240, 480, 960, 1920, 3600, 3600, 3600] {
+            let delay = backoff.next_delay();
+            assert!(delay >= Duration::from_secs(ceiling / 2), "{:?}", delay);
+            assert!(delay <= Duration::from_secs(ceiling), "{:?}", delay);
+        }
+        assert_eq!(backoff.failures(), 10);
+
+        backoff.reset();
+        assert!(backoff.next_delay() <= Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_many_failures_stay_capped() {
+        let mut backoff = Backoff::new(Duration::from_secs(30), Duration::from_secs(3600));
+        for _ in 0..100 {
+            assert!(backoff.next_delay() <= Duration::from_secs(3600));
+        }
+    }
+}
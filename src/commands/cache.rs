@@ -0,0 +1,51 @@
+use anyhow::Result;
+use chrono::Local;
+
+use crate::sun_cache::SunCache;
+
+pub fn show() -> Result<()> {
+    let cache = SunCache::load();
+
+    println!("Sun Times Cache");
+    println!("---------------");
+    println!("Path: {:?}", SunCache::path()?);
+    println!();
+
+    if cache.entries.is_empty() {
+        println!("The cache is empty.");
+        return Ok(());
+    }
+
+    let today = Local::now().date_naive();
+    for entry in &cache.entries {
+        let times = &entry.sun_times;
+        let sun = match (times.sunrise_local(), times.sunset_local()) {
+            (Some(sunrise), Some(sunset)) => format!(
+                "{} - {}",
+                sunrise.format("%H:%M:%S"),
+                sunset.format("%H:%M:%S")
+            ),
+            _ => format!("{:?}", times.kind),
+        };
+        println!(
+            "{}{} {:<19} {:<14} ({:.4}, {:.4})",
+            times.date,
+            if times.date == today { "*" } else { " " },
+            sun,
+            entry.provider,
+            entry.latitude,
+            entry.longitude
+        );
+    }
+
+    Ok(())
+}
+
+pub fn clear() -> Result<()> {
+    if SunCache::clear()? {
+        println!("Sun times cache cleared.");
+    } else {
+        println!("Sun times cache is already empty.");
+    }
+    Ok(())
+}
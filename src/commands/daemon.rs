@@ -1,14 +1,31 @@
 use anyhow::{Context, Result};
+use log::{debug, error, info, warn};
 use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
+use crate::backoff::Backoff;
 use crate::config::Config;
-use crate::sun_times::SunTimes;
+use crate::control::{self, ControlSocket, DaemonStatus, Request, Response};
+use crate::fade;
+use crate::history::{self, HistoryEntry, Trigger};
+use crate::instance::{self, InstanceLock};
+use crate::logging;
+use crate::manual::{self, Override, Pause};
+use crate::notify;
+use crate::resume::{self, ClockReading, SleepEvent, SleepMonitor};
+use crate::schedule::{next_transitions, Schedule, SEARCH_DAYS};
+use crate::service;
+use crate::solar;
+use crate::stability::{Crossed, Stability};
+use crate::sun_times::{self, SunTimes, ThemeMode};
 use crate::theme_switcher::ThemeSwitcher;
+use crate::watch::ConfigWatcher;
 
 pub fn start() -> Result<()> {
     // Check if already running
-    if is_running()? {
-        println!("Daemon is already running.");
+    if let Some(pid) = running_pid()? {
+        println!("Daemon is already running (PID: {}).", pid);
         return Ok(());
     }
 
@@ -21,18 +38,27 @@ pub fn start() -> Result<()> {
     let pid_file = Config::pid_file()?;
     let log_file = Config::log_file()?;
 
+    // Append, so earlier runs stay in the log until it's rotated
+    let log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_file)
+        .with_context(|| format!("Failed to open {:?}", log_file))?;
+
     // Use daemonize to start the daemon
     let daemonize = daemonize::Daemonize::new()
         .pid_file(&pid_file)
         .chown_pid_file(true)
         .working_directory(&state_dir)
-        .stdout(fs::File::create(&log_file)?)
-        .stderr(fs::File::create(&log_file)?);
+        .stdout(log.try_clone()?)
+        .stderr(log);
 
     match daemonize.start() {
         Ok(_) => {
             // We're now in the daemon process
-            run_daemon_loop(config)?;
+            let result = run_daemon_loop(config);
+            let _ = fs::remove_file(&pid_file);
+            result?;
         }
         Err(e) => {
             anyhow::bail!("Failed to daemonize: {}", e);
@@ -42,131 +68,742 @@ pub fn start() -> Result<()> {
     Ok(())
 }
 
-fn run_daemon_loop(config: Config) -> Result<()> {
-    use chrono::Local;
-    use std::thread;
+/// Run the daemon attached to the terminal, for supervisors such as
+/// systemd, launchd or runit and for containers. Logs go to stderr and no
+/// PID file is written.
+pub fn run_foreground() -> Result<()> {
+    if let Some(pid) = running_pid()? {
+        anyhow::bail!("The daemon is already running (PID: {}).", pid);
+    }
+
+    let config = Config::load()?;
+    fs::create_dir_all(Config::state_dir()?)?;
+    run_daemon_loop(config)
+}
+
+/// The first wait before asking the provider again after it failed
+const RETRY_INITIAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// The longest wait between retries. Cached or offline sun times keep the
+/// theme close to right in the meantime.
+const RETRY_MAX: std::time::Duration = std::time::Duration::from_secs(3600);
+
+fn run_daemon_loop(mut config: Config) -> Result<()> {
+    use chrono::{Local, Utc};
     use std::time::Duration;
 
-    let switcher = ThemeSwitcher::new(config.clone());
+    logging::init(&config.log);
+
+    // Held until we return, so a second daemon can't start alongside
+    let _lock = InstanceLock::acquire()?;
+
+    let mut switcher = ThemeSwitcher::new(config.clone());
+    let mut stability = Stability::new(config.stability.clone());
+    let mut applied = switcher.get_current_mode().ok().flatten();
+    let mut applied_phase: Option<String> = None;
+    // For telling a switch after a resume from one on schedule
+    let mut resumed = false;
+    let mut reloaded = None;
+    let mut retry = Backoff::new(RETRY_INITIAL, RETRY_MAX);
+    let mut source = None;
+    install_signal_handlers();
+
+    let mut wakers = Wakers {
+        // Other suntheme commands talk to us here; without it they fall
+        // back to editing the state files and waking us with SIGUSR1
+        control: ControlSocket::bind()
+            .map_err(|e| warn!("Control socket unavailable: {:#}", e))
+            .ok(),
+        // Pick up edits to config.toml without a restart
+        watcher: ConfigWatcher::new(Config::config_path()?),
+        sleep: SleepMonitor::start(),
+    };
+    // Ready once other commands can reach us; working out the schedule
+    // may wait on the network
+    notify::ready("Starting");
 
     loop {
-        // Fetch sun times
-        let sun_times = match SunTimes::get_cached_or_fetch(
-            config.location.latitude,
-            config.location.longitude,
-        ) {
-            Ok(times) => times,
+        if STOPPING.load(Ordering::SeqCst) {
+            notify::stopping();
+            info!("Stopping");
+            return Ok(());
+        }
+
+        // SIGHUP or a change to the file; a broken config keeps the old one
+        if RELOAD.swap(false, Ordering::SeqCst) {
+            match load_valid_config() {
+                Ok(new_config) => reloaded = Some(new_config),
+                Err(e) => error!("Keeping the old config: {:#}", e),
+            }
+        }
+
+        if let Some(new_config) = reloaded.take() {
+            info!("Reloaded config");
+            config = new_config;
+            logging::init(&config.log);
+            switcher = ThemeSwitcher::new(config.clone());
+            stability.configure(config.stability.clone());
+        }
+
+        // While paused, leave the theme alone until the pause ends or
+        // `resume` wakes us
+        if let Some(pause) = Pause::active(Utc::now()) {
+            let status = match pause.until_local() {
+                Some(until) => format!("Paused until {}", until.format("%Y-%m-%d %H:%M:%S")),
+                None => "Paused until resumed".to_string(),
+            };
+            info!("{}", status);
+            notify::status(&status);
+            let sleep_duration = pause
+                .until
+                .and_then(|until| (until - Utc::now()).to_std().ok())
+                .unwrap_or(Duration::from_secs(SEARCH_DAYS as u64 * 86400));
+            resumed = sleep_or_wake(sleep_duration, &mut wakers, |request| {
+                handle_request(&config, request, &mut reloaded)
+            });
+            continue;
+        }
+        if Pause::load().is_some() {
+            info!("Pause expired, resuming automatic switching");
+            if let Err(e) = Pause::clear() {
+                warn!("Failed to clear pause: {}", e);
+            }
+        }
+
+        // Keep the cache filled ahead so switching continues offline
+        let resting = sun_times::provider_retry_in().is_some();
+        let mut prefetch_failed = false;
+        match SunTimes::prefetch(&config, Local::now().date_naive()) {
+            Ok(0) => {}
+            Ok(days) => info!("Prefetched sun times for {} days", days),
             Err(e) => {
-                eprintln!("Failed to get sun times: {}", e);
-                thread::sleep(Duration::from_secs(60));
-                continue;
+                warn!("Failed to prefetch sun times: {}", e);
+                prefetch_failed = true;
             }
-        };
+        }
+
+        // Fetch sun times and resolve today's schedule, falling back to
+        // older or offline times, or failing that fixed times
+        let now = Utc::now();
+        let schedule = Schedule::today(&config).unwrap_or_else(|e| {
+            error!("Failed to work out today's schedule: {:#}", e);
+            Schedule::fallback(&config, Local::now().date_naive())
+        });
+
+        // After the provider fails, leave it alone for longer and longer
+        // and use cached or offline times until it's due another try
+        let fell_back = schedule.source.as_ref().filter(|used| used.is_fallback());
+        if resting {
+            // Not due another try yet, so nothing to learn about it
+        } else if prefetch_failed || fell_back.is_some() {
+            let delay = retry.next_delay();
+            match fell_back {
+                Some(used) => warn!(
+                    "Schedule from {}, retrying in {}s (attempt {})",
+                    used,
+                    delay.as_secs(),
+                    retry.failures()
+                ),
+                None => warn!(
+                    "Retrying the provider in {}s (attempt {})",
+                    delay.as_secs(),
+                    retry.failures()
+                ),
+            }
+            sun_times::retry_provider_in(Some(delay));
+        } else {
+            retry.reset();
+            sun_times::retry_provider_in(None);
+            if let Some(used) = schedule
+                .source
+                .as_ref()
+                .filter(|used| source.as_ref() != Some(*used))
+            {
+                info!("Schedule from {}", used);
+            }
+        }
+        source = schedule.source.clone();
+        let retry_in = sun_times::provider_retry_in();
+
+        // A manual choice from `set` or `toggle` wins until it expires
+        let manual = Override::active(now);
+        if manual.is_none() && Override::load().is_some() {
+            info!("Manual override expired, resuming automatic switching");
+            if let Err(e) = Override::clear() {
+                warn!("Failed to clear override: {}", e);
+            }
+        }
+
+        if let Some(manual) = manual {
+            stability.record_manual(manual.since);
+        }
 
         // Apply current theme based on time
-        let current_mode = sun_times.current_mode();
-        if let Err(e) = switcher.apply_theme(current_mode) {
-            eprintln!("Failed to apply theme: {}", e);
+        let (mut current_mode, mut phase) = match manual {
+            Some(manual) => (manual.mode, None),
+            None => (
+                schedule.mode_at(now),
+                schedule.phase_at(now).map(|p| p.name.as_str()),
+            ),
+        };
+
+        // Hold back automatic switches that come too soon after the last
+        // or while the sun hovers at a threshold, staying in the phase
+        // already applied
+        let mut suppressed = None;
+        let previous_phase = applied_phase.clone();
+        let location = config.location.as_ref().map(|l| (l.latitude, l.longitude));
+        if let Some(previous) = applied.filter(|mode| manual.is_none() && *mode != current_mode) {
+            let held = stability.check(now).or_else(|| {
+                let (lat, lon) = location?;
+                stability.check_band(current_mode, now, |at| solar::elevation(at, lat, lon))
+            });
+            if let Some(held) = held {
+                info!("Suppressed switch to {}: {}", current_mode, held.reason);
+                current_mode = previous;
+                phase = previous_phase.as_deref();
+                suppressed = Some(held);
+            }
+        }
+
+        // Step Ghostty gradually through a switch when fading is enabled
+        let mut fade = match (manual, &suppressed) {
+            (None, None) => fade::fade_at(&config, now).unwrap_or_else(|e| {
+                warn!("Failed to work out fade: {}", e);
+                None
+            }),
+            _ => None,
+        };
+        // `set` and `toggle` apply and record their switch themselves
+        let before = switcher.get_current_mode().ok().flatten();
+        let mut outcome = None;
+        if let Some(step) = &fade {
+            let faded = switcher.apply_fade(current_mode, phase, step);
+            match faded.error() {
+                None => {
+                    info!(
+                        "Fading {} -> {}, step {} of {}",
+                        step.from,
+                        step.to,
+                        step.step + 1,
+                        step.steps
+                    );
+                    outcome = Some(faded);
+                }
+                Some(e) => {
+                    warn!("Failed to fade themes, switching at once: {}", e);
+                    fade = None;
+                }
+            }
+        }
+        let outcome = outcome.unwrap_or_else(|| {
+            let applied = switcher.apply_targets(current_mode, phase);
+            if let Some(e) = applied.error() {
+                error!("Failed to apply theme: {}", e);
+            }
+            applied
+        });
+        if manual.is_none() && applied.is_some_and(|mode| mode != current_mode) {
+            let crossed = location
+                .zip(schedule.crossed_at(now))
+                .map(|((lat, lon), (mode, at))| Crossed {
+                    mode,
+                    at,
+                    elevation: solar::elevation(at, lat, lon),
+                });
+            stability.record_switch(now, crossed);
+        }
+
+        // Keep a record of each switch, for working out later why the
+        // theme changed when it did
+        if before != Some(current_mode) || applied_phase.as_deref() != phase {
+            let trigger = if resumed {
+                Trigger::Resume
+            } else if manual.is_some() {
+                Trigger::Override
+            } else {
+                Trigger::Scheduled
+            };
+            let entry = HistoryEntry::new(now, current_mode, phase, trigger, &outcome);
+            if let Err(e) = history::record(&entry) {
+                warn!("Failed to record switch: {:#}", e);
+            }
         }
+        applied = Some(current_mode);
+        applied_phase = phase.map(String::from);
+        let current = match phase {
+            Some(phase) => format!("{} ({})", phase, current_mode),
+            None => current_mode.to_string(),
+        };
 
-        // Calculate time until next switch
-        let (next_switch, _next_mode) = sun_times.next_switch();
-        let now = Local::now();
+        // Sleep until the next switch, which may be days away
+        let next = match next_transitions(&config, now, 1) {
+            Ok(transitions) => transitions.into_iter().next(),
+            Err(e) => {
+                let delay = retry_in.unwrap_or_else(|| retry.next_delay());
+                error!(
+                    "Failed to find next switch, retrying in {}s: {:#}",
+                    delay.as_secs(),
+                    e
+                );
+                notify::status(&format!(
+                    "Current mode: {}, failed to find next switch",
+                    current
+                ));
+                resumed = sleep_or_wake(delay, &mut wakers, |request| {
+                    handle_request(&config, request, &mut reloaded)
+                });
+                continue;
+            }
+        };
 
-        let sleep_duration = if next_switch > now {
-            (next_switch - now)
-                .to_std()
-                .unwrap_or(Duration::from_secs(60))
-        } else {
-            // If next switch is in the past (tomorrow), sleep until midnight + buffer
-            Duration::from_secs(60)
+        // Wake early for the next fade step, when the next fade begins, when
+        // a held-back switch may go ahead, when the provider is due another
+        // try, or if the override runs out first
+        let fade_at = match (&fade, &next) {
+            (Some(fade), _) => Some(fade.next_step_at()),
+            (None, Some(next))
+                if config.fade.enabled && manual.is_none() && next.mode != current_mode =>
+            {
+                Some(next.at - config.fade.window() / 2).filter(|at| *at > now)
+            }
+            _ => None,
+        };
+        let wake_at = [
+            next.as_ref().map(|t| t.at),
+            manual.map(|m| m.until),
+            fade_at,
+            suppressed.map(|s| s.until),
+            retry_in
+                .and_then(|delay| chrono::Duration::from_std(delay).ok())
+                .map(|delay| now + delay),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        let sleep_duration = match wake_at {
+            Some(at) => (at - Utc::now()).to_std().unwrap_or(Duration::from_secs(1)),
+            // No switch in the search window (e.g. a long polar night)
+            None => Duration::from_secs(SEARCH_DAYS as u64 * 86400),
         };
 
-        // Add a small buffer and cap at reasonable maximum
-        let sleep_duration = sleep_duration
-            .checked_add(Duration::from_secs(5))
-            .unwrap_or(sleep_duration);
-        let sleep_duration = std::cmp::min(sleep_duration, Duration::from_secs(3600)); // Max 1 hour
-
-        eprintln!(
-            "[{}] Current mode: {}, sleeping for {:?} until next check",
-            Local::now().format("%Y-%m-%d %H:%M:%S"),
-            current_mode,
-            sleep_duration
-        );
+        if let Some(manual) = manual {
+            info!(
+                "Manual override: {} until {}",
+                manual.mode,
+                manual.until_local().format("%Y-%m-%d %H:%M:%S")
+            );
+        }
+        match &next {
+            Some(transition) => {
+                info!(
+                    "Current mode: {}, sleeping until {} at {}",
+                    current,
+                    transition.label(),
+                    transition.at_local().format("%Y-%m-%d %H:%M:%S")
+                );
+                notify::status(&format!(
+                    "Current mode: {}, next: {} at {}",
+                    current,
+                    transition.label(),
+                    transition.at_local().format("%a %H:%M")
+                ));
+            }
+            None => {
+                info!(
+                    "Current mode: {}, no switch in the next {} days",
+                    current, SEARCH_DAYS
+                );
+                notify::status(&format!("Current mode: {}", current));
+            }
+        }
+
+        resumed = sleep_or_wake(sleep_duration, &mut wakers, |request| {
+            handle_request(&config, request, &mut reloaded)
+        });
+    }
+}
+
+/// Set when another suntheme command asks the daemon to re-evaluate
+static WOKEN: AtomicBool = AtomicBool::new(false);
+
+/// Set on SIGTERM or SIGINT so the loop can finish cleanly
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+/// Set on SIGHUP, or when the config file changes, to re-read the config
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_wake(_signal: libc::c_int) {
+    WOKEN.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn handle_stop(_signal: libc::c_int) {
+    STOPPING.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn handle_reload(_signal: libc::c_int) {
+    RELOAD.store(true, Ordering::SeqCst);
+}
 
-        thread::sleep(sleep_duration);
+fn install_signal_handlers() {
+    install_handler(libc::SIGUSR1, handle_wake);
+    install_handler(libc::SIGTERM, handle_stop);
+    install_handler(libc::SIGINT, handle_stop);
+    install_handler(libc::SIGHUP, handle_reload);
+}
+
+fn install_handler(signal: libc::c_int, handler: extern "C" fn(libc::c_int)) {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as *const () as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(signal, &action, std::ptr::null_mut());
+    }
+}
+
+/// Everything besides signals that can end the daemon's sleep early
+struct Wakers {
+    control: Option<ControlSocket>,
+    watcher: ConfigWatcher,
+    sleep: Option<SleepMonitor>,
+}
+
+/// Sleep for `duration`, answering control requests with `handle`.
+/// Returns early if woken with SIGUSR1, asked to stop or reload, sent a
+/// request that changes what the daemon should do, or the config changes.
+/// Also returns on resume from suspend or when the clock or timezone
+/// changes, since the deadline may no longer mean what it did, and then
+/// returns `true`.
+fn sleep_or_wake(
+    duration: std::time::Duration,
+    wakers: &mut Wakers,
+    mut handle: impl FnMut(Request) -> Response,
+) -> bool {
+    let Wakers {
+        control,
+        watcher,
+        sleep,
+    } = wakers;
+    let started = ClockReading::now();
+    let deadline = Instant::now() + duration;
+    while !WOKEN.swap(false, Ordering::SeqCst) {
+        if STOPPING.load(Ordering::SeqCst) || RELOAD.load(Ordering::SeqCst) {
+            break;
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+
+        let mut fds: Vec<libc::pollfd> = control
+            .as_ref()
+            .map(ControlSocket::fd)
+            .into_iter()
+            .chain(watcher.fd())
+            .chain(sleep.as_ref().and_then(SleepMonitor::fd))
+            .map(|fd| libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+        let timeout = watcher
+            .poll_interval()
+            .map_or(remaining, |interval| remaining.min(interval))
+            .min(resume::CHECK_INTERVAL);
+        let timeout = timeout.as_millis().clamp(1, i32::MAX as u128) as libc::c_int;
+        // Returns early with EINTR when a signal arrives
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+
+        let mut resumed = false;
+        for event in sleep.as_mut().map(SleepMonitor::events).unwrap_or_default() {
+            let message = match event {
+                SleepEvent::Suspending => "Going to sleep",
+                SleepEvent::Resumed => "Resumed from sleep",
+            };
+            info!("{}", message);
+            resumed |= event == SleepEvent::Resumed;
+        }
+        let clock_change = started.changes(&ClockReading::now());
+        if let Some(change) = &clock_change {
+            info!("{}", change);
+        }
+        if resumed || clock_change.is_some() {
+            return true;
+        }
+
+        if watcher.changed() {
+            debug!("Config file changed");
+            RELOAD.store(true, Ordering::SeqCst);
+            break;
+        }
+        if let (true, Some(control)) = (ready > 0, control.as_ref()) {
+            if control.serve(&mut handle) {
+                break;
+            }
+        }
+    }
+    false
+}
+
+/// Answer a control request. Changes go to the state files, which the loop
+/// reads when it re-evaluates; a new config is passed back in `reloaded`.
+fn handle_request(config: &Config, request: Request, reloaded: &mut Option<Config>) -> Response {
+    debug!("Control request: {:?}", request);
+    let now = chrono::Utc::now();
+    let current_mode = || {
+        ThemeSwitcher::new(config.clone())
+            .get_current_mode()
+            .ok()
+            .flatten()
+    };
+
+    let response = match request {
+        Request::GetStatus => Ok(Response::Status(DaemonStatus {
+            pid: std::process::id(),
+            mode: current_mode(),
+            pause: Pause::active(now),
+            manual: Override::active(now),
+            next: next_transitions(config, now, 1)
+                .ok()
+                .and_then(|transitions| transitions.into_iter().next()),
+        })),
+        // Applied here rather than by the loop, which leaves the theme alone
+        // while paused
+        Request::SetMode {
+            mode,
+            until,
+            duration,
+        } => manual::hold(config, mode, now, until.as_deref(), duration.as_deref()).map(|held| {
+            Response::Mode {
+                mode: held.mode,
+                until: held.until,
+            }
+        }),
+        Request::Toggle { until, duration } => {
+            let mode = current_mode().map_or(ThemeMode::Dark, |mode| mode.opposite());
+            manual::hold(config, mode, now, until.as_deref(), duration.as_deref()).map(|held| {
+                Response::Mode {
+                    mode: held.mode,
+                    until: held.until,
+                }
+            })
+        }
+        Request::Pause { duration } => Pause::start(now, duration.as_deref())
+            .map(|pause| Response::Paused { until: pause.until }),
+        Request::Resume => Pause::clear().map(|was_paused| Response::Resumed { was_paused }),
+        Request::ReloadConfig => load_valid_config().map(|new_config| {
+            *reloaded = Some(new_config);
+            Response::Reloaded
+        }),
+        Request::NextTransitions { count } => next_transitions(config, now, count)
+            .map(|transitions| Response::Transitions { transitions }),
+    };
+
+    response.unwrap_or_else(|e| Response::Error {
+        message: format!("{:#}", e),
+    })
+}
+
+/// Read the config file again, refusing one that can't be used
+fn load_valid_config() -> Result<Config> {
+    let config = Config::load()?;
+    config.validate().context("Invalid config")?;
+    Ok(config)
+}
+
+/// Ask a running daemon to re-read its state, e.g. after a manual override
+pub fn wake() {
+    if let Ok(Some(pid)) = running_pid() {
+        unsafe {
+            libc::kill(pid, libc::SIGUSR1);
+        }
     }
 }
 
 pub fn stop() -> Result<()> {
     let pid_file = Config::pid_file()?;
 
-    if !pid_file.exists() {
-        println!("Daemon is not running (no PID file).");
+    let Some(pid) = running_pid()? else {
+        if pid_file.exists() {
+            let _ = fs::remove_file(&pid_file);
+            println!("Daemon was not running (stale PID file).");
+        } else {
+            println!("Daemon is not running.");
+        }
         return Ok(());
+    };
+
+    // Never signal a process that only looks like ours
+    if !instance::is_suntheme(pid) {
+        anyhow::bail!(
+            "PID {} is not a suntheme process, so it was left alone. Remove {:?} if the daemon is gone.",
+            pid,
+            pid_file
+        );
     }
 
-    let pid_str = fs::read_to_string(&pid_file).context("Failed to read PID file")?;
-    let pid: i32 = pid_str.trim().parse().context("Invalid PID in PID file")?;
-
     // Send SIGTERM to the process
     unsafe {
-        if libc::kill(pid, libc::SIGTERM) == 0 {
-            println!("Daemon stopped (PID: {}).", pid);
-        } else {
+        if libc::kill(pid, libc::SIGTERM) != 0 {
             let err = std::io::Error::last_os_error();
-            if err.raw_os_error() == Some(libc::ESRCH) {
-                println!("Daemon was not running (stale PID file).");
-            } else {
+            if err.raw_os_error() != Some(libc::ESRCH) {
                 anyhow::bail!("Failed to stop daemon: {}", err);
             }
         }
     }
 
+    // Wait for it to finish so `restart` doesn't race the old daemon
+    let deadline = Instant::now() + STOP_TIMEOUT;
+    while running_pid()? == Some(pid) {
+        if Instant::now() >= deadline {
+            anyhow::bail!(
+                "Daemon (PID: {}) did not stop within {} seconds.",
+                pid,
+                STOP_TIMEOUT.as_secs()
+            );
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+
     // Remove PID file
     let _ = fs::remove_file(&pid_file);
+    println!("Daemon stopped (PID: {}).", pid);
+    if service::launchd_agent_installed() {
+        println!(
+            "The launch agent starts it again at the next login. Run 'suntheme service uninstall' to stop that."
+        );
+    }
 
     Ok(())
 }
 
+/// How long `stop` waits for the daemon to exit
+const STOP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Stop the daemon if it's running and start it again, e.g. after an upgrade
+pub fn restart() -> Result<()> {
+    if let Some(pid) = running_pid()? {
+        // Only `start` writes a PID file. Any other daemon belongs to a
+        // service manager or a terminal, and a copy started here would
+        // run outside it.
+        if get_pid().ok().flatten() != Some(pid) {
+            if super::service::restart()? {
+                return Ok(());
+            }
+            anyhow::bail!(
+                "The daemon (PID: {}) was started with 'run --foreground'. Restart it where it runs, or stop it with 'suntheme stop' first.",
+                pid
+            );
+        }
+        stop()?;
+    }
+    start()
+}
+
+/// Ask the running daemon to re-read the config file
+pub fn reload() -> Result<()> {
+    match control::request(&Request::ReloadConfig)? {
+        Some(response) => {
+            response.into_result()?;
+            println!("Daemon reloaded the config.");
+        }
+        // An older daemon without a control socket still reloads on SIGHUP
+        None => match running_pid()? {
+            Some(pid) => {
+                unsafe {
+                    libc::kill(pid, libc::SIGHUP);
+                }
+                println!("Asked the daemon to reload the config (PID: {}).", pid);
+            }
+            None => println!("Daemon is not running."),
+        },
+    }
+    Ok(())
+}
+
 pub fn status() -> Result<()> {
     let config = Config::load().ok();
-    let running = is_running()?;
+
+    // Ask the live daemon first, falling back to the PID and state files
+    let live = match control::request(&Request::GetStatus) {
+        Ok(Some(Response::Status(status))) => Some(status),
+        _ => None,
+    };
+    let now = chrono::Utc::now();
 
     println!("Suntheme Status");
     println!("---------------");
 
-    if running {
-        let pid = get_pid()?;
-        println!("Daemon:  running (PID: {})", pid.unwrap_or(0));
-    } else {
-        println!("Daemon:  not running");
+    match &live {
+        Some(live) => println!("Daemon:  running (PID: {})", live.pid),
+        None => match running_pid()? {
+            Some(pid) => println!("Daemon:  running (PID: {})", pid),
+            None => println!("Daemon:  not running"),
+        },
     }
 
     // Show current theme state
     if let Some(cfg) = &config {
-        let switcher = ThemeSwitcher::new(cfg.clone());
-        if let Ok(Some(mode)) = switcher.get_current_mode() {
-            println!("Theme:   {}", mode);
-        } else {
-            println!("Theme:   unknown");
+        let mode = match &live {
+            Some(live) => live.mode,
+            None => ThemeSwitcher::new(cfg.clone())
+                .get_current_mode()
+                .ok()
+                .flatten(),
+        };
+        match mode {
+            Some(mode) => println!("Theme:   {}", mode),
+            None => println!("Theme:   unknown"),
+        }
+        let (pause, manual) = match &live {
+            Some(live) => (live.pause, live.manual),
+            None => (Pause::active(now), Override::active(now)),
+        };
+        if let Some(pause) = pause {
+            match pause.until_local() {
+                Some(until) => println!("Paused:  until {}", until.format("%a %Y-%m-%d %H:%M")),
+                None => println!("Paused:  until 'suntheme resume'"),
+            }
+        }
+        if let Some(manual) = manual {
+            println!(
+                "Override: {} until {}",
+                manual.mode,
+                manual.until_local().format("%a %Y-%m-%d %H:%M")
+            );
         }
 
         // Show sun times if available
-        if let Ok(sun_times) =
-            SunTimes::get_cached_or_fetch(cfg.location.latitude, cfg.location.longitude)
-        {
+        if let Ok(schedule) = Schedule::today(cfg) {
             println!();
-            println!("Sunrise: {}", sun_times.sunrise_local().format("%H:%M:%S"));
-            println!("Sunset:  {}", sun_times.sunset_local().format("%H:%M:%S"));
+            super::sun::print_sun_times(&schedule);
+            if !schedule.phases.is_empty() {
+                super::sun::print_phases(&schedule);
+            } else if schedule.is_custom() {
+                super::sun::print_switch_times(cfg, &schedule);
+            }
 
-            let (next_switch, next_mode) = sun_times.next_switch();
-            println!(
-                "Next:    {} at {}",
-                next_mode,
-                next_switch.format("%H:%M:%S")
-            );
+            let now = chrono::Utc::now();
+            if let Some(exception) = schedule.exception_at(now) {
+                println!(
+                    "Pinned:  {} until {} (exception)",
+                    exception.mode,
+                    exception.end_local().format("%a %Y-%m-%d %H:%M")
+                );
+            }
+
+            match next_transitions(cfg, now, 1) {
+                Ok(transitions) => match transitions.first() {
+                    Some(next) => println!(
+                        "Next:    {} at {}",
+                        next.label(),
+                        super::sun::format_switch_time(next)
+                    ),
+                    None => println!("Next:    no switch in the next {} days", SEARCH_DAYS),
+                },
+                Err(e) => println!("Next:    unknown ({})", e),
+            }
         }
     } else {
         println!();
@@ -176,12 +813,17 @@ pub fn status() -> Result<()> {
     Ok(())
 }
 
-fn is_running() -> Result<bool> {
-    let pid = get_pid()?;
-    match pid {
-        Some(p) => Ok(process_exists(p)),
-        None => Ok(false),
+/// The PID of the running daemon. The instance lock is the authority; a
+/// PID file on its own, from a daemon older than the lock, only counts if
+/// it names a live suntheme process.
+fn running_pid() -> Result<Option<i32>> {
+    if let Some(pid) = InstanceLock::holder()? {
+        return Ok(Some(pid));
     }
+    Ok(get_pid()
+        .ok()
+        .flatten()
+        .filter(|pid| process_exists(*pid) && instance::is_suntheme(*pid)))
 }
 
 fn get_pid() -> Result<Option<i32>> {
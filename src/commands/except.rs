@@ -0,0 +1,79 @@
+use anyhow::Result;
+
+use crate::config::{parse_local_datetime, Config, Exception};
+use crate::sun_times::ThemeMode;
+
+pub fn add(mode: ThemeMode, start: &str, end: &str, note: Option<String>) -> Result<()> {
+    let mut config = Config::load()?;
+
+    let start = parse_local_datetime(start, false)?;
+    let end = parse_local_datetime(end, true)?;
+    if end <= start {
+        anyhow::bail!("The exception must end after it starts.");
+    }
+
+    config.exceptions.push(Exception {
+        start,
+        end,
+        mode,
+        note,
+    });
+    config.exceptions.sort_by_key(|e| e.start);
+    config.save()?;
+
+    println!(
+        "Added exception: {} from {} until {}.",
+        mode,
+        start.format("%Y-%m-%d %H:%M"),
+        end.format("%Y-%m-%d %H:%M")
+    );
+    Ok(())
+}
+
+pub fn list() -> Result<()> {
+    let config = Config::load()?;
+
+    println!("Exceptions");
+    println!("----------");
+
+    if config.exceptions.is_empty() {
+        println!("No exceptions configured.");
+        return Ok(());
+    }
+
+    for (index, exception) in config.exceptions.iter().enumerate() {
+        println!(
+            "{:>2}. {} - {}  {:<5} {}",
+            index + 1,
+            exception.start.format("%Y-%m-%d %H:%M"),
+            exception.end.format("%Y-%m-%d %H:%M"),
+            exception.mode,
+            exception.note.as_deref().unwrap_or("")
+        );
+    }
+
+    Ok(())
+}
+
+/// Remove the exception at `index`, as numbered by `list`
+pub fn remove(index: usize) -> Result<()> {
+    let mut config = Config::load()?;
+
+    if index == 0 || index > config.exceptions.len() {
+        anyhow::bail!(
+            "No exception #{}. Run 'suntheme except list' to see them.",
+            index
+        );
+    }
+
+    let removed = config.exceptions.remove(index - 1);
+    config.save()?;
+
+    println!(
+        "Removed exception: {} from {} until {}.",
+        removed.mode,
+        removed.start.format("%Y-%m-%d %H:%M"),
+        removed.end.format("%Y-%m-%d %H:%M")
+    );
+    Ok(())
+}
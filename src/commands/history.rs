@@ -0,0 +1,52 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+
+use crate::history::{self, HistoryEntry};
+
+/// Print the switches of the last `days` days, oldest first
+pub fn run(days: u32, json: bool) -> Result<()> {
+    // So many days back that there's no such time means everything
+    let since = Duration::try_days(days as i64)
+        .and_then(|back| Utc::now().checked_sub_signed(back))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+    let entries = history::since(since)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        println!("No theme switches in the last {} days.", days);
+        return Ok(());
+    }
+
+    println!("Switch history");
+    println!("--------------");
+    for entry in &entries {
+        print_entry(entry);
+    }
+    Ok(())
+}
+
+fn print_entry(entry: &HistoryEntry) {
+    let switched_to = match &entry.phase {
+        Some(phase) => format!("{} ({})", phase, entry.mode),
+        None => entry.mode.to_string(),
+    };
+    let targets = if entry.targets.is_empty() {
+        "nothing switched".to_string()
+    } else {
+        entry.targets.join(", ")
+    };
+    println!(
+        "{}  {:<16} {:<9}  {}",
+        entry.at_local().format("%a %Y-%m-%d %H:%M:%S"),
+        switched_to,
+        entry.trigger,
+        targets
+    );
+    for error in &entry.errors {
+        println!("{:<25}{} failed: {}", "", error.target, error.error);
+    }
+}
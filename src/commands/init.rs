@@ -1,9 +1,11 @@
 use anyhow::Result;
+use chrono::NaiveTime;
 use dialoguer::{Confirm, Input, Select};
 
 use crate::banner;
-use crate::config::{Config, Location, ThemePair, Themes};
-use crate::sun_times::{geocode_location, SunTimes};
+use crate::config::{Config, FixedTimes, Location, ScheduleMode};
+use crate::schedule::Schedule;
+use crate::sun_times::geocode_location;
 use crate::telemetry;
 use crate::theme_switcher::ThemeSwitcher;
 use crate::themes::{get_theme_presets, setup_neovim_integration};
@@ -47,10 +49,13 @@ pub fn run() -> Result<()> {
         None
     };
 
-    let (latitude, longitude) = if let Some(ref config) = existing_config {
+    // Start from the existing config so other settings are kept
+    let mut config = existing_config.clone().unwrap_or_default();
+
+    if let Some(ref existing) = existing_config {
         // Config exists - ask what they want to do
         let options = vec![
-            "Change themes only (keep current location)",
+            "Change themes only (keep current schedule)",
             "Full setup (reconfigure everything)",
             "Cancel",
         ];
@@ -63,18 +68,19 @@ pub fn run() -> Result<()> {
 
         match selection {
             0 => {
-                // Keep existing location, skip to theme selection
-                println!(
-                    "\nKeeping location: ({:.4}, {:.4})\n",
-                    config.location.latitude, config.location.longitude
-                );
-                (config.location.latitude, config.location.longitude)
+                // Keep existing schedule, skip to theme selection
+                match (existing.schedule.mode, &existing.location) {
+                    (ScheduleMode::Sun, Some(location)) => println!(
+                        "\nKeeping location: ({:.4}, {:.4})\n",
+                        location.latitude, location.longitude
+                    ),
+                    (mode, _) => println!("\nKeeping {} schedule\n", mode),
+                }
             }
             1 => {
-                // Full setup - get new location
-                println!("\n--- Location Setup ---\n");
-                println!("Enter your location for sunrise/sunset calculations.\n");
-                get_location()?
+                // Full setup - choose a new schedule
+                println!();
+                setup_schedule(&mut config)?;
             }
             _ => {
                 println!("Setup cancelled.");
@@ -82,11 +88,9 @@ pub fn run() -> Result<()> {
             }
         }
     } else {
-        // No existing config - do full location setup
-        println!("--- Location Setup ---\n");
-        println!("Enter your location for sunrise/sunset calculations.\n");
-        get_location()?
-    };
+        // No existing config - do full schedule setup
+        setup_schedule(&mut config)?;
+    }
 
     // Theme selection with presets
     println!("--- Theme Setup ---\n");
@@ -103,24 +107,13 @@ pub fn run() -> Result<()> {
         ask_telemetry_consent()?
     };
 
-    // Create and save config
-    let config = Config {
-        location: Location {
-            latitude,
-            longitude,
-        },
-        themes: Themes {
-            ghostty: ThemePair {
-                light: ghostty_light,
-                dark: ghostty_dark,
-            },
-            neovim: ThemePair {
-                light: neovim_light,
-                dark: neovim_dark,
-            },
-        },
-        telemetry: Some(telemetry_enabled),
-    };
+    // Save config
+    // Only light and dark are chosen here; keep any phase themes
+    config.themes.ghostty.light = ghostty_light;
+    config.themes.ghostty.dark = ghostty_dark;
+    config.themes.neovim.light = neovim_light;
+    config.themes.neovim.dark = neovim_dark;
+    config.telemetry = Some(telemetry_enabled);
 
     config.save()?;
     println!("Config saved to {:?}", Config::config_path()?);
@@ -144,19 +137,17 @@ pub fn run() -> Result<()> {
 
     // Apply theme based on current sun position
     println!("\nApplying theme based on current time...");
-    match SunTimes::get_cached_or_fetch(latitude, longitude) {
-        Ok(sun_times) => {
-            let current_mode = sun_times.current_mode();
+    match Schedule::today(&config) {
+        Ok(schedule) => {
+            let now = chrono::Utc::now();
+            let current_mode = schedule.mode_at(now);
+            let phase = schedule.phase_at(now).map(|p| p.name.as_str());
             let switcher = ThemeSwitcher::new(config);
 
-            match switcher.apply_theme(current_mode) {
+            match switcher.apply(current_mode, phase) {
                 Ok(_) => {
                     println!("Applied {} theme.", current_mode);
-                    println!(
-                        "Sunrise: {} | Sunset: {}",
-                        sun_times.sunrise_local().format("%H:%M"),
-                        sun_times.sunset_local().format("%H:%M")
-                    );
+                    super::sun::print_sun_times(&schedule);
                 }
                 Err(e) => {
                     println!("Warning: Could not apply theme: {}", e);
@@ -197,6 +188,71 @@ fn ask_telemetry_consent() -> Result<bool> {
     Ok(telemetry_enabled)
 }
 
+/// Ask whether to follow the sun or fixed clock times, then set up either
+/// the location or the times
+fn setup_schedule(config: &mut Config) -> Result<()> {
+    println!("--- Schedule Setup ---\n");
+    let options = vec![
+        "Follow sunrise/sunset at my location",
+        "Fixed clock times (ignore the sun)",
+    ];
+
+    let selection = Select::new()
+        .with_prompt("  How should themes switch?")
+        .items(&options)
+        .default(0)
+        .interact()?;
+
+    if selection == 0 {
+        println!("\n--- Location Setup ---\n");
+        println!("Enter your location for sunrise/sunset calculations.\n");
+        let (latitude, longitude) = get_location()?;
+        config.location = Some(Location {
+            latitude,
+            longitude,
+        });
+        config.schedule.mode = ScheduleMode::Sun;
+    } else {
+        println!();
+        let times = get_fixed_times()?;
+        println!(
+            "\nLight from {} to {}\n",
+            times.light.format("%H:%M"),
+            times.dark.format("%H:%M")
+        );
+        config.schedule.fixed = Some(times);
+        config.schedule.mode = ScheduleMode::Fixed;
+    }
+
+    Ok(())
+}
+
+fn get_fixed_times() -> Result<FixedTimes> {
+    loop {
+        let light = get_clock_time("  Switch to light at (HH:MM)", "08:00")?;
+        let dark = get_clock_time("  Switch to dark at (HH:MM)", "18:00")?;
+
+        if light < dark {
+            return Ok(FixedTimes { light, dark });
+        }
+        println!("The light time must be before the dark time. Please try again.\n");
+    }
+}
+
+fn get_clock_time(prompt: &str, default: &str) -> Result<NaiveTime> {
+    loop {
+        let input: String = Input::new()
+            .with_prompt(prompt)
+            .default(default.to_string())
+            .interact_text()?;
+
+        match NaiveTime::parse_from_str(input.trim(), "%H:%M") {
+            Ok(time) => return Ok(time),
+            Err(_) => println!("Invalid time: {}. Use HH:MM, e.g. 08:30.\n", input),
+        }
+    }
+}
+
 fn get_location() -> Result<(f64, f64)> {
     loop {
         let location_query: String = Input::new()
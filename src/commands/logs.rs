@@ -0,0 +1,116 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, TimeZone, Utc};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config::{parse_local_datetime, Config};
+use crate::logging;
+use crate::manual::parse_duration;
+
+/// Print the end of the daemon log, or everything since `since` including
+/// rotated logs, then keep printing new lines with `follow`
+pub fn run(follow: bool, since: Option<&str>, lines: usize) -> Result<()> {
+    let path = Config::log_file()?;
+    if !path.exists() {
+        println!("No daemon log at {:?}.", path);
+        println!("A daemon run with 'run --foreground' logs to stderr instead; under systemd,");
+        println!("see 'journalctl --user -u suntheme'.");
+        return Ok(());
+    }
+
+    let printed = match since {
+        Some(since) => print_since(&path, parse_since(since)?),
+        None => print_last(&path, lines),
+    };
+    let printed = printed.and_then(|()| if follow { follow_log(&path) } else { Ok(()) });
+
+    // Piping into `head` closes stdout early, which is fine
+    match printed {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        printed => printed.with_context(|| format!("Failed to read {:?}", path)),
+    }
+}
+
+/// `--since` takes a duration back from now, e.g. `2h`, or a local time
+fn parse_since(since: &str) -> Result<DateTime<Local>> {
+    if let Ok(duration) = parse_duration(since) {
+        // Further back than a date can go means the whole log
+        return Ok(Local::now()
+            .checked_sub_signed(duration)
+            .unwrap_or(DateTime::<Utc>::MIN_UTC.with_timezone(&Local)));
+    }
+    let time = parse_local_datetime(since, false).map_err(|_| {
+        anyhow::anyhow!(
+            "Invalid --since: {}. Use a duration such as 2h or a time such as '2024-06-10 09:00'.",
+            since
+        )
+    })?;
+    Local
+        .from_local_datetime(&time)
+        .earliest()
+        .context("That time doesn't exist in the local timezone")
+}
+
+fn print_since(path: &Path, since: DateTime<Local>) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    for file in logging::log_files(path) {
+        let file = File::open(file)?;
+        // Lines without a time, such as a panic, go with the entry before
+        let mut show = false;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if let Some(time) = logging::line_time(&line) {
+                show = time >= since;
+            }
+            if show {
+                writeln!(stdout, "{}", line)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_last(path: &Path, lines: usize) -> io::Result<()> {
+    let content = fs::read(path)?;
+    let content = String::from_utf8_lossy(&content);
+    let all: Vec<&str> = content.lines().collect();
+    let mut stdout = io::stdout().lock();
+    for line in &all[all.len().saturating_sub(lines)..] {
+        writeln!(stdout, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Print lines as the daemon writes them until interrupted, starting over
+/// on the new file when the log is rotated
+fn follow_log(path: &Path) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    let mut position = file.seek(SeekFrom::End(0))?;
+    let mut inode = file.metadata()?.ino();
+    let mut buf = Vec::new();
+
+    loop {
+        std::thread::sleep(Duration::from_millis(500));
+
+        match fs::metadata(path) {
+            Ok(meta) if meta.ino() != inode || meta.len() < position => {
+                file = File::open(path)?;
+                inode = meta.ino();
+                position = 0;
+            }
+            Ok(_) => {}
+            // Between rotating and the daemon's next line
+            Err(_) => continue,
+        }
+
+        buf.clear();
+        file.seek(SeekFrom::Start(position))?;
+        position += file.read_to_end(&mut buf)? as u64;
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&buf)?;
+        stdout.flush()?;
+    }
+}
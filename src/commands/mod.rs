@@ -1,4 +1,11 @@
+pub mod cache;
 pub mod daemon;
+pub mod except;
+pub mod history;
 pub mod init;
+pub mod logs;
+pub mod pause;
+pub mod schedule;
+pub mod service;
 pub mod sun;
 pub mod theme;
@@ -0,0 +1,55 @@
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+
+use super::daemon;
+use crate::control::{self, Request, Response};
+use crate::manual::Pause;
+
+pub fn pause(duration: Option<&str>) -> Result<()> {
+    let request = Request::Pause {
+        duration: duration.map(String::from),
+    };
+    let until = match control::request(&request)? {
+        Some(response) => match response.into_result()? {
+            Response::Paused { until } => until,
+            other => anyhow::bail!("Unexpected reply from the daemon: {:?}", other),
+        },
+        None => {
+            let pause = Pause::start(Utc::now(), duration)?;
+            daemon::wake();
+            pause.until
+        }
+    };
+
+    match until.map(|until: DateTime<Utc>| until.with_timezone(&Local)) {
+        Some(until) => println!(
+            "Automatic switching paused until {}.",
+            until.format("%a %H:%M")
+        ),
+        None => println!("Automatic switching paused. Run 'suntheme resume' to continue."),
+    }
+    Ok(())
+}
+
+pub fn resume() -> Result<()> {
+    let was_paused = match control::request(&Request::Resume)? {
+        Some(response) => match response.into_result()? {
+            Response::Resumed { was_paused } => was_paused,
+            other => anyhow::bail!("Unexpected reply from the daemon: {:?}", other),
+        },
+        None => {
+            let was_paused = Pause::clear()?;
+            if was_paused {
+                daemon::wake();
+            }
+            was_paused
+        }
+    };
+
+    if was_paused {
+        println!("Automatic switching resumed.");
+    } else {
+        println!("Automatic switching is not paused.");
+    }
+    Ok(())
+}
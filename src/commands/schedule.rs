@@ -0,0 +1,47 @@
+use anyhow::Result;
+use chrono::{Duration, Local};
+
+use crate::config::Config;
+use crate::schedule::Schedule;
+
+/// Print the resolved plan for the next `days` days
+pub fn run(days: usize) -> Result<()> {
+    let config = Config::load()?;
+    let today = Local::now().date_naive();
+
+    println!("Schedule");
+    println!("--------");
+
+    for offset in 0..days.max(1) as i64 {
+        let date = today + Duration::days(offset);
+        let plan = match Schedule::for_date(&config, date) {
+            Ok(schedule) => describe(&schedule),
+            Err(e) => format!("unknown ({})", e),
+        };
+        let rule = if config.schedule.rule_for(date).is_some() {
+            "*"
+        } else {
+            " "
+        };
+        println!("{}{} {}", date.format("%a %Y-%m-%d"), rule, plan);
+    }
+
+    if !config.schedule.rules.is_empty() {
+        println!();
+        println!("* set by a schedule rule");
+    }
+
+    Ok(())
+}
+
+fn describe(schedule: &Schedule) -> String {
+    let plan = match (schedule.light_at_local(), schedule.dark_at_local()) {
+        (Some(light_at), Some(dark_at)) => format!(
+            "light {}  dark {}",
+            light_at.format("%H:%M"),
+            dark_at.format("%H:%M")
+        ),
+        _ => format!("{} all day", schedule.mode_at(schedule.day_start).as_str()),
+    };
+    format!("{:<6} {}", schedule.mode.as_str(), plan)
+}
@@ -0,0 +1,220 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::process::Command;
+
+use crate::config::Config;
+use crate::service::{
+    launchd_agent_installed, launchd_plist_path, systemd_unit, systemd_unit_path, LaunchAgent,
+    LAUNCHD_LABEL, SYSTEMD_UNIT,
+};
+
+pub fn install() -> Result<()> {
+    if cfg!(target_os = "macos") {
+        install_launchd()
+    } else {
+        install_systemd()
+    }
+}
+
+pub fn uninstall() -> Result<()> {
+    if cfg!(target_os = "macos") {
+        uninstall_launchd()
+    } else {
+        uninstall_systemd()
+    }
+}
+
+pub fn status() -> Result<()> {
+    if cfg!(target_os = "macos") {
+        status_launchd()
+    } else {
+        status_systemd()
+    }
+}
+
+/// Restart the daemon through the installed service, so it stays under
+/// the service manager. Returns false when no service is installed.
+pub fn restart() -> Result<bool> {
+    if cfg!(target_os = "macos") {
+        if !launchd_agent_installed() {
+            return Ok(false);
+        }
+        let target = format!("{}/{}", launchd_domain(), LAUNCHD_LABEL);
+        launchctl(&["kickstart", "-k", &target])?;
+        println!("Restarted {}.", LAUNCHD_LABEL);
+    } else {
+        if !systemd_unit_path()?.exists() || !systemctl_available() {
+            return Ok(false);
+        }
+        systemctl(&["restart", SYSTEMD_UNIT])?;
+        println!("Restarted {}.", SYSTEMD_UNIT);
+    }
+    Ok(true)
+}
+
+fn install_systemd() -> Result<()> {
+    let exe = std::env::current_exe().context("Could not find the suntheme executable")?;
+    let unit = systemd_unit(&exe);
+
+    if !systemctl_available() {
+        println!("systemctl --user is not available. Save this unit as");
+        println!("{:?} and enable it yourself:\n", systemd_unit_path()?);
+        print!("{}", unit);
+        return Ok(());
+    }
+
+    let path = systemd_unit_path()?;
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(&path, unit).with_context(|| format!("Failed to write {:?}", path))?;
+    println!("Wrote {:?}", path);
+
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", "--now", SYSTEMD_UNIT])?;
+    println!("Enabled and started {}.", SYSTEMD_UNIT);
+    println!("If you used 'suntheme start' before, run 'suntheme stop' to avoid two daemons.");
+    Ok(())
+}
+
+fn uninstall_systemd() -> Result<()> {
+    let path = systemd_unit_path()?;
+    if !path.exists() {
+        println!("The systemd unit is not installed.");
+        return Ok(());
+    }
+
+    if systemctl_available() {
+        systemctl(&["disable", "--now", SYSTEMD_UNIT])?;
+    }
+    fs::remove_file(&path).with_context(|| format!("Failed to remove {:?}", path))?;
+    if systemctl_available() {
+        systemctl(&["daemon-reload"])?;
+    }
+
+    println!("Removed {:?}", path);
+    Ok(())
+}
+
+fn status_systemd() -> Result<()> {
+    let path = systemd_unit_path()?;
+
+    println!("Suntheme Service");
+    println!("----------------");
+    if path.exists() {
+        println!("Unit:    {:?}", path);
+    } else {
+        println!("Unit:    not installed. Run 'suntheme service install'.");
+        return Ok(());
+    }
+
+    if !systemctl_available() {
+        println!("systemctl --user is not available.");
+        return Ok(());
+    }
+
+    // systemd's own view, including the daemon's status line
+    println!();
+    Command::new("systemctl")
+        .args(["--user", "status", "--no-pager", SYSTEMD_UNIT])
+        .status()
+        .context("Failed to run systemctl")?;
+    Ok(())
+}
+
+/// Whether a systemd user manager is reachable
+fn systemctl_available() -> bool {
+    Command::new("systemctl")
+        .args(["--user", "show-environment"])
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+fn systemctl(args: &[&str]) -> Result<()> {
+    let status = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .status()
+        .context("Failed to run systemctl")?;
+    if !status.success() {
+        anyhow::bail!("systemctl --user {} failed", args.join(" "));
+    }
+    Ok(())
+}
+
+fn install_launchd() -> Result<()> {
+    let exe = std::env::current_exe().context("Could not find the suntheme executable")?;
+    let agent = LaunchAgent::new(&exe, &Config::log_file()?);
+
+    let path = launchd_plist_path()?;
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::create_dir_all(Config::state_dir()?)?;
+    fs::write(&path, agent.to_plist()).with_context(|| format!("Failed to write {:?}", path))?;
+    println!("Wrote {:?}", path);
+
+    // Replace an agent loaded from an earlier install
+    let _ = launchctl(&["bootout", &launchd_domain(), &path.to_string_lossy()]);
+    launchctl(&["bootstrap", &launchd_domain(), &path.to_string_lossy()])?;
+    println!("Loaded {}. It will start at every login.", LAUNCHD_LABEL);
+    println!("If you used 'suntheme start' before, run 'suntheme stop' to avoid two daemons.");
+    Ok(())
+}
+
+fn uninstall_launchd() -> Result<()> {
+    let path = launchd_plist_path()?;
+    if !path.exists() {
+        println!("The launchd agent is not installed.");
+        return Ok(());
+    }
+
+    let _ = launchctl(&["bootout", &launchd_domain(), &path.to_string_lossy()]);
+    fs::remove_file(&path).with_context(|| format!("Failed to remove {:?}", path))?;
+
+    println!("Removed {:?}", path);
+    Ok(())
+}
+
+fn status_launchd() -> Result<()> {
+    let path = launchd_plist_path()?;
+
+    println!("Suntheme Service");
+    println!("----------------");
+    if !path.exists() {
+        println!("Agent:   not installed. Run 'suntheme service install'.");
+        return Ok(());
+    }
+
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+    let agent = LaunchAgent::parse(&content)?;
+    println!("Agent:   {:?}", path);
+    println!("Command: {}", agent.program_arguments.join(" "));
+    if let Some(log_path) = &agent.log_path {
+        println!("Log:     {:?}", log_path);
+    }
+
+    let loaded = Command::new("launchctl")
+        .args(["print", &format!("{}/{}", launchd_domain(), agent.label)])
+        .output()
+        .is_ok_and(|output| output.status.success());
+    println!("Loaded:  {}", if loaded { "yes" } else { "no" });
+    Ok(())
+}
+
+/// The launchd domain of the logged-in user's GUI session
+fn launchd_domain() -> String {
+    format!("gui/{}", unsafe { libc::getuid() })
+}
+
+fn launchctl(args: &[&str]) -> Result<()> {
+    let output = Command::new("launchctl")
+        .args(args)
+        .output()
+        .context("Failed to run launchctl")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "launchctl {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
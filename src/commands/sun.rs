@@ -1,36 +1,184 @@
 use anyhow::Result;
+use chrono::{Local, Utc};
 
-use crate::config::Config;
-use crate::sun_times::SunTimes;
+use crate::config::{Config, ScheduleMode};
+use crate::schedule::{next_transitions, Schedule, Transition, SEARCH_DAYS};
 
-pub fn run() -> Result<()> {
+pub fn run(next: usize) -> Result<()> {
     let config = Config::load()?;
 
-    println!(
-        "Fetching sun times for ({}, {})...\n",
-        config.location.latitude, config.location.longitude
-    );
+    let now = Utc::now();
+    let schedule = Schedule::today(&config)?;
+    let current_mode = schedule.mode_at(now);
+    // Today can still be shown when later days can't be worked out
+    let transitions = next_transitions(&config, now, next.max(1));
 
-    let sun_times =
-        SunTimes::get_cached_or_fetch(config.location.latitude, config.location.longitude)?;
-
-    let sunrise = sun_times.sunrise_local();
-    let sunset = sun_times.sunset_local();
-    let current_mode = sun_times.current_mode();
-    let (next_switch, next_mode) = sun_times.next_switch();
-
-    println!("Today's Sun Times");
-    println!("-----------------");
-    println!("Sunrise: {}", sunrise.format("%H:%M:%S"));
-    println!("Sunset:  {}", sunset.format("%H:%M:%S"));
+    if schedule.sun_times.is_none() {
+        println!(
+            "Using the {} schedule today (the sun is ignored).\n",
+            schedule.mode
+        );
+        println!("Today's Schedule");
+        println!("----------------");
+        print_switch_times(&config, &schedule);
+    } else {
+        let location = config.require_location()?;
+        println!(
+            "Calculating sun times for ({}, {})...\n",
+            location.latitude, location.longitude
+        );
+        println!("Today's Sun Times");
+        println!("-----------------");
+        print_sun_times(&schedule);
+        if !schedule.phases.is_empty() {
+            println!();
+            print_phases(&schedule);
+        } else if schedule.is_custom() {
+            println!();
+            print_switch_times(&config, &schedule);
+        }
+    }
     println!();
     println!("Current mode: {}", current_mode);
-    println!(
-        "Next switch:  {} -> {} at {}",
-        current_mode,
-        next_mode,
-        next_switch.format("%H:%M:%S")
-    );
+    if let Some(phase) = schedule.phase_at(now) {
+        println!("Phase:        {}", phase.name);
+    }
+    if let Some(exception) = schedule.exception_at(now) {
+        println!(
+            "Exception:    {} until {}",
+            exception.mode,
+            exception.end_local().format("%a %Y-%m-%d %H:%M")
+        );
+    }
+    match transitions.as_ref().map(|transitions| transitions.first()) {
+        Ok(Some(first)) => println!(
+            "Next switch:  {} -> {} at {}",
+            current_mode,
+            first.label(),
+            format_switch_time(first)
+        ),
+        Ok(None) => println!(
+            "Next switch:  none in the next {} days, staying {}",
+            SEARCH_DAYS, current_mode
+        ),
+        Err(e) => println!("Next switch:  unknown ({:#})", e),
+    }
+
+    let transitions = transitions.unwrap_or_default();
+    if next > 1 && !transitions.is_empty() {
+        println!();
+        println!("Upcoming switches");
+        println!("-----------------");
+        for transition in &transitions {
+            println!(
+                "{}  {}",
+                transition.at_local().format("%a %Y-%m-%d %H:%M:%S"),
+                transition.label()
+            );
+        }
+    }
 
     Ok(())
 }
+
+/// Format a switch time, including the weekday when it isn't today
+pub fn format_switch_time(transition: &Transition) -> String {
+    let at = transition.at_local();
+    if at.date_naive() == Local::now().date_naive() {
+        at.format("%H:%M:%S").to_string()
+    } else {
+        at.format("%a %H:%M:%S").to_string()
+    }
+}
+
+/// Print sunrise and sunset, or an explanation of polar day or night
+pub fn print_sun_times(schedule: &Schedule) {
+    let Some(sun_times) = &schedule.sun_times else {
+        println!("Schedule: {} (sun times not used today)", schedule.mode);
+        return;
+    };
+    match (sun_times.sunrise_local(), sun_times.sunset_local()) {
+        (Some(sunrise), Some(sunset)) => {
+            println!("Sunrise: {}", sunrise.format("%H:%M:%S"));
+            println!("Sunset:  {}", sunset.format("%H:%M:%S"));
+        }
+        _ => {
+            if let Some(description) = sun_times.polar_description() {
+                println!("{}", description);
+            }
+        }
+    }
+    if let Some(source) = schedule.source.as_ref().filter(|s| s.is_fallback()) {
+        println!("Source:  {} (the provider is unavailable)", source);
+    }
+}
+
+/// Print when each of today's phases begins
+pub fn print_phases(schedule: &Schedule) {
+    for phase in &schedule.phases {
+        println!(
+            "{:<8} {} ({})",
+            format!("{}:", phase.name),
+            phase.at_local().format("%H:%M:%S"),
+            phase.mode
+        );
+    }
+}
+
+/// Print the effective switching times, along with the raw threshold
+/// events when offsets or clamps moved them
+pub fn print_switch_times(config: &Config, schedule: &Schedule) {
+    let (light_at, dark_at) = match (schedule.light_at_local(), schedule.dark_at_local()) {
+        (Some(light_at), Some(dark_at)) => (light_at, dark_at),
+        _ => {
+            match (schedule.mode, schedule.steady) {
+                (ScheduleMode::Sun, Some(mode)) => {
+                    println!(
+                        "The {} and {} thresholds are not reached today.",
+                        config.schedule.light_at, config.schedule.dark_at
+                    );
+                    println!("Holding {} mode all day.", mode);
+                }
+                (_, Some(mode)) => println!("Holding {} mode all day.", mode),
+                _ => {}
+            }
+            return;
+        }
+    };
+
+    if schedule.is_fixed() {
+        println!("Light:   {} (fixed)", light_at.format("%H:%M:%S"));
+        println!("Dark:    {} (fixed)", dark_at.format("%H:%M:%S"));
+    } else if schedule.uses_fallback() {
+        println!("Light:   {} (polar fallback)", light_at.format("%H:%M:%S"));
+        println!("Dark:    {} (polar fallback)", dark_at.format("%H:%M:%S"));
+    } else if let (true, Some(raw_light), Some(raw_dark)) = (
+        schedule.is_adjusted(),
+        schedule.raw_light_at_local(),
+        schedule.raw_dark_at_local(),
+    ) {
+        println!(
+            "Light:   {} ({} at {})",
+            light_at.format("%H:%M:%S"),
+            config.schedule.light_at,
+            raw_light.format("%H:%M:%S")
+        );
+        println!(
+            "Dark:    {} ({} at {})",
+            dark_at.format("%H:%M:%S"),
+            config.schedule.dark_at,
+            raw_dark.format("%H:%M:%S")
+        );
+    } else {
+        println!(
+            "Light:   {} ({})",
+            light_at.format("%H:%M:%S"),
+            config.schedule.light_at
+        );
+        println!(
+            "Dark:    {} ({})",
+            dark_at.format("%H:%M:%S"),
+            config.schedule.dark_at
+        );
+    }
+}
@@ -1,24 +1,51 @@
 use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
 use dialoguer::{Input, Select};
 
-use crate::config::{Config, ThemePair};
+use super::daemon;
+use crate::config::Config;
+use crate::control::{self, Request, Response};
+use crate::manual;
 use crate::sun_times::ThemeMode;
 use crate::theme_switcher::ThemeSwitcher;
 use crate::themes::get_theme_presets;
 
-pub fn set(mode: ThemeMode) -> Result<()> {
-    let config = Config::load()?;
-    let switcher = ThemeSwitcher::new(config);
-
-    switcher.apply_theme(mode)?;
+pub fn set(mode: ThemeMode, until: Option<&str>, duration: Option<&str>) -> Result<()> {
+    let request = Request::SetMode {
+        mode,
+        until: until.map(String::from),
+        duration: duration.map(String::from),
+    };
+    let (mode, until) = match control::request(&request)? {
+        Some(response) => held(response)?,
+        None => (mode, hold(&Config::load()?, mode, until, duration)?),
+    };
 
-    println!("Theme set to: {}", mode);
+    println!(
+        "Theme set to: {} until {}",
+        mode,
+        until.with_timezone(&Local).format("%a %H:%M")
+    );
     Ok(())
 }
 
-pub fn toggle() -> Result<()> {
+pub fn toggle(until: Option<&str>, duration: Option<&str>) -> Result<()> {
+    let request = Request::Toggle {
+        until: until.map(String::from),
+        duration: duration.map(String::from),
+    };
+    if let Some(response) = control::request(&request)? {
+        let (new_mode, until) = held(response)?;
+        println!(
+            "Theme toggled to: {} until {}",
+            new_mode,
+            until.with_timezone(&Local).format("%a %H:%M")
+        );
+        return Ok(());
+    }
+
     let config = Config::load()?;
-    let switcher = ThemeSwitcher::new(config);
+    let switcher = ThemeSwitcher::new(config.clone());
 
     let current = switcher.get_current_mode()?;
     let new_mode = match current {
@@ -26,12 +53,38 @@ pub fn toggle() -> Result<()> {
         None => ThemeMode::Dark, // Default to dark if no state exists
     };
 
-    switcher.apply_theme(new_mode)?;
+    let until = hold(&config, new_mode, until, duration)?;
 
-    println!("Theme toggled to: {}", new_mode);
+    println!(
+        "Theme toggled to: {} until {}",
+        new_mode,
+        until.with_timezone(&Local).format("%a %H:%M")
+    );
     Ok(())
 }
 
+/// The mode and expiry from the daemon's reply to `set` or `toggle`
+fn held(response: Response) -> Result<(ThemeMode, DateTime<Utc>)> {
+    match response.into_result()? {
+        Response::Mode { mode, until } => Ok((mode, until)),
+        other => anyhow::bail!("Unexpected reply from the daemon: {:?}", other),
+    }
+}
+
+/// Apply `mode` and save it as an override so the daemon keeps it until it
+/// expires. Used when no daemon is listening on the control socket.
+fn hold(
+    config: &Config,
+    mode: ThemeMode,
+    until: Option<&str>,
+    duration: Option<&str>,
+) -> Result<DateTime<Utc>> {
+    let held = manual::hold(config, mode, Utc::now(), until, duration)?;
+    daemon::wake();
+
+    Ok(held.until)
+}
+
 pub fn configure_themes() -> Result<()> {
     let mut config = Config::load()?;
 
@@ -49,15 +102,11 @@ pub fn configure_themes() -> Result<()> {
 
     let (ghostty_light, ghostty_dark, neovim_light, neovim_dark) = select_theme_preset()?;
 
-    config.themes.ghostty = ThemePair {
-        light: ghostty_light,
-        dark: ghostty_dark,
-    };
-
-    config.themes.neovim = ThemePair {
-        light: neovim_light,
-        dark: neovim_dark,
-    };
+    // Only light and dark are chosen here; keep any phase themes
+    config.themes.ghostty.light = ghostty_light;
+    config.themes.ghostty.dark = ghostty_dark;
+    config.themes.neovim.light = neovim_light;
+    config.themes.neovim.dark = neovim_dark;
 
     config.save()?;
 
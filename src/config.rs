@@ -1,14 +1,237 @@
 use anyhow::{Context, Result};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 
+use crate::schedule::SolarEvent;
+use crate::sun_times::ThemeMode;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
-    pub location: Location,
+    /// Only needed for sun-based schedules
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
     pub themes: Themes,
     #[serde(default)]
     pub telemetry: Option<bool>,
+    #[serde(default)]
+    pub provider: ProviderConfig,
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub fade: FadeConfig,
+    #[serde(default)]
+    pub stability: StabilityConfig,
+    #[serde(default)]
+    pub log: LogConfig,
+    /// Date ranges pinned to one mode, overriding the schedule
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exceptions: Vec<Exception>,
+    /// Named phases of the day, replacing plain light/dark switching on
+    /// sun-based days
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub phases: Vec<Phase>,
+}
+
+/// Hold one mode from `start` until `end` (local time), e.g. for a
+/// conference or vacation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Exception {
+    #[serde(with = "local_datetime")]
+    pub start: NaiveDateTime,
+    #[serde(
+        serialize_with = "local_datetime::serialize",
+        deserialize_with = "local_datetime::deserialize_end"
+    )]
+    pub end: NaiveDateTime,
+    pub mode: ThemeMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Parse a local date and time such as `2024-06-10 09:00`. A bare date
+/// means the start of that day, or the end of it when `end` is set, so
+/// date ranges include their last day.
+pub fn parse_local_datetime(s: &str, end: bool) -> Result<NaiveDateTime> {
+    let s = s.trim();
+    for format in [
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+    ] {
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(s, format) {
+            return Ok(datetime);
+        }
+    }
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d").with_context(|| {
+        format!(
+            "Invalid date/time: {}. Use YYYY-MM-DD or YYYY-MM-DD HH:MM.",
+            s
+        )
+    })?;
+    let date = if end { date + Duration::days(1) } else { date };
+    Ok(date.and_time(NaiveTime::MIN))
+}
+
+mod local_datetime {
+    use chrono::NaiveDateTime;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    const FORMAT: &str = "%Y-%m-%d %H:%M";
+
+    pub fn serialize<S: Serializer>(at: &NaiveDateTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&at.format(FORMAT).to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<NaiveDateTime, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        super::parse_local_datetime(&s, false).map_err(serde::de::Error::custom)
+    }
+
+    pub fn deserialize_end<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<NaiveDateTime, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        super::parse_local_datetime(&s, true).map_err(serde::de::Error::custom)
+    }
+}
+
+/// How much sun time data to keep cached for HTTP providers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// Days ahead the daemon prefetches, so it keeps switching offline
+    #[serde(default = "default_days_ahead")]
+    pub days_ahead: u32,
+}
+
+fn default_days_ahead() -> u32 {
+    14
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            days_ahead: default_days_ahead(),
+        }
+    }
+}
+
+/// Blend Ghostty between the light and dark palettes around each switch
+/// instead of changing at once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FadeConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Length of the fade, centred on the switch
+    #[serde(default = "default_fade_minutes")]
+    pub minutes: u32,
+    /// Number of intermediate themes shown during the fade
+    #[serde(default = "default_fade_steps")]
+    pub steps: u32,
+}
+
+fn default_fade_minutes() -> u32 {
+    40
+}
+
+fn default_fade_steps() -> u32 {
+    20
+}
+
+impl Default for FadeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            minutes: default_fade_minutes(),
+            steps: default_fade_steps(),
+        }
+    }
+}
+
+impl FadeConfig {
+    pub fn window(&self) -> Duration {
+        Duration::minutes(self.minutes.max(1) as i64)
+    }
+
+    pub fn steps(&self) -> u32 {
+        self.steps.max(1)
+    }
+}
+
+/// The widest hysteresis band. The sun climbs at most 15° an hour, and far
+/// less near the poles, so a wider one could hold a switch back for hours.
+const MAX_HYSTERESIS_DEGREES: f64 = 10.0;
+
+/// Limits that stop the theme flapping between light and dark
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StabilityConfig {
+    /// Minimum minutes between automatic switches
+    #[serde(default)]
+    pub min_switch_minutes: u32,
+    /// Degrees the sun must pass the `light_at` or `dark_at` threshold by
+    /// before switching
+    #[serde(default)]
+    pub hysteresis_degrees: f64,
+    /// Minutes after a manual `set` or `toggle` during which automatic
+    /// switches are held back
+    #[serde(default)]
+    pub manual_grace_minutes: u32,
+}
+
+/// How the daemon writes its log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogConfig {
+    /// Filter in `RUST_LOG` syntax, e.g. `info` or
+    /// `info,suntheme::schedule=debug`. `RUST_LOG` itself takes precedence.
+    #[serde(default = "default_log_level")]
+    pub level: String,
+    #[serde(default)]
+    pub format: LogFormat,
+    /// Size at which `daemon.log` is rotated
+    #[serde(default = "default_log_max_size_kb")]
+    pub max_size_kb: u64,
+    /// Rotated logs to keep, as `daemon.log.1` (newest) and up
+    #[serde(default = "default_log_keep")]
+    pub keep: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line, for log collectors
+    Json,
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_log_max_size_kb() -> u64 {
+    1024
+}
+
+fn default_log_keep() -> u32 {
+    3
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: default_log_level(),
+            format: LogFormat::default(),
+            max_size_kb: default_log_max_size_kb(),
+            keep: default_log_keep(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,30 +246,299 @@ pub struct Themes {
     pub neovim: ThemePair,
 }
 
+/// Theme names for one target. `light` and `dark` are required; any other
+/// keys name themes for phases, e.g. `dusk = "rose-pine-moon"`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ThemePair {
     pub light: String,
     pub dark: String,
+    #[serde(flatten)]
+    pub phases: BTreeMap<String, String>,
+}
+
+impl ThemePair {
+    pub fn new(light: impl Into<String>, dark: impl Into<String>) -> Self {
+        Self {
+            light: light.into(),
+            dark: dark.into(),
+            phases: BTreeMap::new(),
+        }
+    }
+
+    /// The theme for `phase`, falling back to the one for `mode` when the
+    /// phase has none of its own
+    pub fn theme_for(&self, mode: ThemeMode, phase: Option<&str>) -> &str {
+        if let Some(theme) = phase.and_then(|name| self.phases.get(name)) {
+            return theme;
+        }
+        match mode {
+            ThemeMode::Light => &self.light,
+            ThemeMode::Dark => &self.dark,
+        }
+    }
+}
+
+/// A named part of the day with its own themes, such as dawn or golden hour.
+/// Each phase lasts until the next one begins.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Phase {
+    pub name: String,
+    /// When the phase begins
+    pub at: PhaseStart,
+    /// Whether the phase counts as light or dark. This picks the theme when
+    /// a target has none for the phase, and the Neovim background.
+    pub mode: ThemeMode,
+    /// Which crossing of an elevation angle begins the phase, since the
+    /// sun passes each angle twice a day. Required for angles only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<SunDirection>,
+}
+
+/// Which way the sun is moving when it crosses an elevation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SunDirection {
+    Rising,
+    Setting,
+}
+
+/// A local clock time such as `"07:30"`, or a solar event as accepted by
+/// `light_at`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PhaseStart {
+    Time(NaiveTime),
+    Event(SolarEvent),
+}
+
+impl std::fmt::Display for PhaseStart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PhaseStart::Time(time) => write!(f, "{}", time.format("%H:%M")),
+            PhaseStart::Event(event) => write!(f, "{}", event),
+        }
+    }
+}
+
+/// Where sun times come from
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    #[serde(default)]
+    pub kind: ProviderKind,
+    /// Base URL override for HTTP providers (e.g. a local stand-in server)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Path to a JSON file of sun times, used by the fixture provider
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProviderKind {
+    /// Compute sun times offline
+    #[default]
+    Local,
+    /// api.sunrise-sunset.org
+    SunriseSunset,
+    /// Open-Meteo forecast API
+    OpenMeteo,
+    /// Static times read from a file
+    Fixture,
+}
+
+/// When to switch between light and dark
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleConfig {
+    #[serde(default)]
+    pub mode: ScheduleMode,
+    /// Clock times used when `mode = "fixed"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fixed: Option<FixedTimes>,
+    #[serde(default = "default_light_at")]
+    pub light_at: SolarEvent,
+    #[serde(default = "default_dark_at")]
+    pub dark_at: SolarEvent,
+    /// Minutes to shift the light switch by (negative = earlier)
+    #[serde(default)]
+    pub light_offset_minutes: i64,
+    /// Minutes to shift the dark switch by (negative = earlier)
+    #[serde(default)]
+    pub dark_offset_minutes: i64,
+    /// Never switch to light before this local time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub earliest_light: Option<NaiveTime>,
+    /// Never switch to dark after this local time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latest_dark: Option<NaiveTime>,
+    /// Clock times to switch at on days the thresholds are never crossed
+    /// (polar day and night). Without it the mode is held all day.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub polar_fallback: Option<FixedTimes>,
+    /// Per-day overrides of `mode`, e.g. dark all day on Sundays
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<ScheduleRule>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduleMode {
+    /// Follow the sun at the configured location
+    #[default]
+    Sun,
+    /// Switch at the clock times in `[schedule.fixed]`
+    Fixed,
+    /// Hold light mode all day
+    Light,
+    /// Hold dark mode all day
+    Dark,
+}
+
+impl ScheduleMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScheduleMode::Sun => "sun",
+            ScheduleMode::Fixed => "fixed",
+            ScheduleMode::Light => "light",
+            ScheduleMode::Dark => "dark",
+        }
+    }
+}
+
+impl std::fmt::Display for ScheduleMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Use a different schedule mode on certain days of the week or dates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleRule {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<Weekday>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dates: Vec<NaiveDate>,
+    pub mode: ScheduleMode,
+    /// Clock times for a fixed rule, overriding `[schedule.fixed]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub light: Option<NaiveTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dark: Option<NaiveTime>,
+}
+
+impl ScheduleRule {
+    fn fixed_times(&self) -> Option<FixedTimes> {
+        Some(FixedTimes {
+            light: self.light?,
+            dark: self.dark?,
+        })
+    }
+}
+
+/// Local clock times for switching, independent of the sun
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixedTimes {
+    pub light: NaiveTime,
+    pub dark: NaiveTime,
+}
+
+impl FixedTimes {
+    fn checked(self) -> Result<Self> {
+        if self.light >= self.dark {
+            anyhow::bail!(
+                "Fixed light time ({}) must be before the dark time ({})",
+                self.light.format("%H:%M"),
+                self.dark.format("%H:%M")
+            );
+        }
+        Ok(self)
+    }
+}
+
+fn default_light_at() -> SolarEvent {
+    SolarEvent::Sunrise
+}
+
+fn default_dark_at() -> SolarEvent {
+    SolarEvent::Sunset
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            mode: ScheduleMode::Sun,
+            fixed: None,
+            light_at: default_light_at(),
+            dark_at: default_dark_at(),
+            light_offset_minutes: 0,
+            dark_offset_minutes: 0,
+            earliest_light: None,
+            latest_dark: None,
+            polar_fallback: None,
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl ScheduleConfig {
+    /// The clock times for `mode = "fixed"`
+    pub fn fixed_times(&self) -> Result<FixedTimes> {
+        self.fixed
+            .context(
+                "Fixed schedule needs switching times. Add a [schedule.fixed] section with light and dark times.",
+            )?
+            .checked()
+    }
+
+    /// The rule that applies on `date`. Rules listing the date itself take
+    /// precedence over day-of-week rules; otherwise the first match wins.
+    pub fn rule_for(&self, date: NaiveDate) -> Option<&ScheduleRule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.dates.contains(&date))
+            .or_else(|| {
+                self.rules
+                    .iter()
+                    .find(|rule| rule.days.contains(&date.weekday()))
+            })
+    }
+
+    /// The schedule mode in effect on `date` after applying rules
+    pub fn mode_on(&self, date: NaiveDate) -> ScheduleMode {
+        self.rule_for(date).map_or(self.mode, |rule| rule.mode)
+    }
+
+    /// The fixed clock times on `date`, from its rule if it sets them
+    pub fn fixed_times_on(&self, date: NaiveDate) -> Result<FixedTimes> {
+        match self.rule_for(date).and_then(ScheduleRule::fixed_times) {
+            Some(times) => times.checked(),
+            None => self.fixed_times(),
+        }
+    }
+
+    /// Whether any day can follow the sun, and so needs a location
+    pub fn uses_sun(&self) -> bool {
+        self.mode == ScheduleMode::Sun || self.rules.iter().any(|r| r.mode == ScheduleMode::Sun)
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            location: Location {
-                latitude: 0.0,
-                longitude: 0.0,
-            },
+            location: None,
             themes: Themes {
-                ghostty: ThemePair {
-                    light: "rose-pine-dawn".to_string(),
-                    dark: "rose-pine".to_string(),
-                },
-                neovim: ThemePair {
-                    light: "rose-pine-dawn".to_string(),
-                    dark: "rose-pine".to_string(),
-                },
+                ghostty: ThemePair::new("rose-pine-dawn", "rose-pine"),
+                neovim: ThemePair::new("rose-pine-dawn", "rose-pine"),
             },
             telemetry: None,
+            provider: ProviderConfig::default(),
+            schedule: ScheduleConfig::default(),
+            cache: CacheConfig::default(),
+            fade: FadeConfig::default(),
+            stability: StabilityConfig::default(),
+            log: LogConfig::default(),
+            exceptions: Vec::new(),
+            phases: Vec::new(),
         }
     }
 }
@@ -70,6 +562,18 @@ impl Config {
         Ok(cache_dir)
     }
 
+    pub fn data_dir() -> Result<PathBuf> {
+        let data_dir = dirs::data_dir()
+            .context("Could not determine data directory")?
+            .join("suntheme");
+        Ok(data_dir)
+    }
+
+    /// Where generated Ghostty themes, such as fade steps, are written
+    pub fn generated_themes_dir() -> Result<PathBuf> {
+        Ok(Self::data_dir()?.join("themes"))
+    }
+
     pub fn state_dir() -> Result<PathBuf> {
         // On macOS, use ~/Library/Application Support/suntheme
         // On Linux, use ~/.local/state/suntheme
@@ -91,6 +595,26 @@ impl Config {
         Ok(Self::state_dir()?.join("daemon.log"))
     }
 
+    pub fn socket_file() -> Result<PathBuf> {
+        Ok(Self::state_dir()?.join("daemon.sock"))
+    }
+
+    pub fn lock_file() -> Result<PathBuf> {
+        Ok(Self::state_dir()?.join("daemon.lock"))
+    }
+
+    pub fn override_file() -> Result<PathBuf> {
+        Ok(Self::state_dir()?.join("override.json"))
+    }
+
+    pub fn pause_file() -> Result<PathBuf> {
+        Ok(Self::state_dir()?.join("pause.json"))
+    }
+
+    pub fn history_file() -> Result<PathBuf> {
+        Ok(Self::state_dir()?.join("history.jsonl"))
+    }
+
     pub fn load() -> Result<Self> {
         let path = Self::config_path()?;
         if !path.exists() {
@@ -103,6 +627,81 @@ impl Config {
         Ok(config)
     }
 
+    /// Check settings that parse but can't work, such as a fixed schedule
+    /// without times. The daemon runs this before taking a reloaded config.
+    pub fn validate(&self) -> Result<()> {
+        if self.schedule.uses_sun() {
+            self.require_location()?;
+        }
+        if self.schedule.mode == ScheduleMode::Fixed {
+            self.schedule.fixed_times()?;
+        }
+        for rule in &self.schedule.rules {
+            // One time alone would be dropped for `[schedule.fixed]`'s pair
+            if rule.light.is_some() != rule.dark.is_some() {
+                anyhow::bail!(
+                    "A schedule rule sets only one of light and dark. Set both, or neither to use [schedule.fixed]."
+                );
+            }
+            if rule.mode == ScheduleMode::Fixed {
+                match rule.fixed_times() {
+                    Some(times) => times.checked().map(|_| ())?,
+                    None => self.schedule.fixed_times().map(|_| ())?,
+                }
+            }
+        }
+        for phase in &self.phases {
+            match (phase.at, phase.direction) {
+                (PhaseStart::Event(SolarEvent::Elevation(_)), None) => anyhow::bail!(
+                    "Phase {} starts at {}, which the sun passes twice a day. \
+                     Set direction = \"rising\" or \"setting\".",
+                    phase.name,
+                    phase.at
+                ),
+                (PhaseStart::Event(SolarEvent::Elevation(_)), Some(_)) | (_, None) => {}
+                (_, Some(_)) => anyhow::bail!(
+                    "Phase {} has a direction, which only applies to elevation angles",
+                    phase.name
+                ),
+            }
+        }
+        // Catch misspelt phase names, which would otherwise be ignored
+        for (target, pair) in [
+            ("ghostty", &self.themes.ghostty),
+            ("neovim", &self.themes.neovim),
+        ] {
+            if let Some(name) = pair
+                .phases
+                .keys()
+                .find(|name| !self.phases.iter().any(|phase| phase.name == **name))
+            {
+                anyhow::bail!(
+                    "[themes.{}] has a theme for {}, but there's no phase of that name",
+                    target,
+                    name
+                );
+            }
+        }
+        let hysteresis = self.stability.hysteresis_degrees;
+        if !(0.0..=MAX_HYSTERESIS_DEGREES).contains(&hysteresis) {
+            anyhow::bail!(
+                "Invalid hysteresis_degrees: {}. Use 0 to {}.",
+                hysteresis,
+                MAX_HYSTERESIS_DEGREES
+            );
+        }
+        crate::logging::Filter::parse(&self.log.level)?;
+        for exception in &self.exceptions {
+            if exception.end <= exception.start {
+                anyhow::bail!(
+                    "Exception from {} must end after it starts",
+                    exception.start.format("%Y-%m-%d %H:%M")
+                );
+            }
+        }
+        Ok(())
+    }
+
     pub fn save(&self) -> Result<()> {
         let path = Self::config_path()?;
         let dir = path.parent().unwrap();
@@ -114,6 +713,13 @@ impl Config {
         Ok(())
     }
 
+    /// The configured location, required for sun-based schedules
+    pub fn require_location(&self) -> Result<&Location> {
+        self.location
+            .as_ref()
+            .context("No location configured. Run 'suntheme init' to set one.")
+    }
+
     pub fn exists() -> Result<bool> {
         Ok(Self::config_path()?.exists())
     }
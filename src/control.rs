@@ -0,0 +1,272 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::manual::{Override, Pause};
+use crate::schedule::Transition;
+use crate::sun_times::ThemeMode;
+
+/// How long either side waits on the other before giving up
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A request to the daemon, sent as one line of JSON, e.g.
+/// `{"command":"set-mode","mode":"dark","duration":"1h"}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    GetStatus,
+    SetMode {
+        mode: ThemeMode,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        until: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration: Option<String>,
+    },
+    Toggle {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        until: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration: Option<String>,
+    },
+    Pause {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration: Option<String>,
+    },
+    Resume,
+    ReloadConfig,
+    NextTransitions {
+        #[serde(default = "default_count")]
+        count: usize,
+    },
+}
+
+fn default_count() -> usize {
+    1
+}
+
+impl Request {
+    /// Whether the daemon has to re-evaluate the theme after this request
+    pub fn changes_state(&self) -> bool {
+        !matches!(self, Request::GetStatus | Request::NextTransitions { .. })
+    }
+}
+
+/// The daemon's reply to a `Request`, sent as one line of JSON
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "kebab-case")]
+pub enum Response {
+    Status(DaemonStatus),
+    /// The mode now held by a manual override
+    Mode {
+        mode: ThemeMode,
+        until: DateTime<Utc>,
+    },
+    Paused {
+        until: Option<DateTime<Utc>>,
+    },
+    Resumed {
+        was_paused: bool,
+    },
+    Reloaded,
+    Transitions {
+        transitions: Vec<Transition>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl Response {
+    /// Turn an error reply into an `Err`
+    pub fn into_result(self) -> Result<Self> {
+        match self {
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            response => Ok(response),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    pub pid: u32,
+    /// The mode last applied
+    pub mode: Option<ThemeMode>,
+    #[serde(default)]
+    pub pause: Option<Pause>,
+    #[serde(default)]
+    pub manual: Option<Override>,
+    #[serde(default)]
+    pub next: Option<Transition>,
+}
+
+/// Send `request` to the running daemon. Returns `None` when no daemon is
+/// listening, so callers can act on their own instead.
+pub fn request(request: &Request) -> Result<Option<Response>> {
+    let path = Config::socket_file()?;
+    let stream = match UnixStream::connect(&path) {
+        Ok(stream) => stream,
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+            ) =>
+        {
+            return Ok(None)
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to connect to {:?}", path)),
+    };
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    (&stream)
+        .write_all(line.as_bytes())
+        .context("Failed to send request to the daemon")?;
+
+    let mut reply = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut reply)
+        .context("Failed to read the daemon's reply")?;
+    let response = serde_json::from_str(&reply).context("Invalid reply from the daemon")?;
+    Ok(Some(response))
+}
+
+/// The daemon's end of the control socket
+pub struct ControlSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    /// Listen on the socket in the state directory, replacing a stale one
+    /// left by a daemon that didn't exit cleanly
+    pub fn bind() -> Result<Self> {
+        let path = Config::socket_file()?;
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                anyhow::bail!("Another daemon is already listening on {:?}", path);
+            }
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove stale socket {:?}", path))?;
+        }
+
+        let listener =
+            UnixListener::bind(&path).with_context(|| format!("Failed to listen on {:?}", path))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener, path })
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+
+    /// Answer every pending connection with `handle`, returning whether any
+    /// request changed the daemon's state
+    pub fn serve(&self, mut handle: impl FnMut(Request) -> Response) -> bool {
+        let mut changed = false;
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::warn!("Failed to accept control connection: {}", e);
+                    break;
+                }
+            };
+            match Self::answer(&stream, &mut handle) {
+                Ok(request_changed) => changed |= request_changed,
+                Err(e) => log::warn!("Failed to answer control request: {}", e),
+            }
+        }
+        changed
+    }
+
+    fn answer(stream: &UnixStream, handle: &mut impl FnMut(Request) -> Response) -> Result<bool> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line)?;
+        let (response, changed) = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let changed = request.changes_state();
+                // A bug handling one request mustn't take the daemon down
+                let response = panic::catch_unwind(AssertUnwindSafe(|| handle(request)))
+                    .unwrap_or_else(|_| Response::Error {
+                        message: "The daemon failed to handle the request".to_string(),
+                    });
+                (response, changed)
+            }
+            Err(e) => (
+                Response::Error {
+                    message: format!("Invalid request: {}", e),
+                },
+                false,
+            ),
+        };
+
+        let mut reply = serde_json::to_string(&response)?;
+        reply.push('\n');
+        let mut stream = stream;
+        stream.write_all(reply.as_bytes())?;
+        Ok(changed)
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_json() {
+        let request: Request =
+            serde_json::from_str(r#"{"command":"set-mode","mode":"dark","duration":"1h"}"#)
+                .unwrap();
+        assert_eq!(
+            request,
+            Request::SetMode {
+                mode: ThemeMode::Dark,
+                until: None,
+                duration: Some("1h".to_string()),
+            }
+        );
+
+        let request: Request = serde_json::from_str(r#"{"command":"next-transitions"}"#).unwrap();
+        assert_eq!(request, Request::NextTransitions { count: 1 });
+        assert!(!request.changes_state());
+
+        assert_eq!(
+            serde_json::to_string(&Request::ReloadConfig).unwrap(),
+            r#"{"command":"reload-config"}"#
+        );
+        assert!(serde_json::from_str::<Request>(r#"{"command":"explode"}"#).is_err());
+    }
+
+    #[test]
+    fn test_response_json() {
+        let response = Response::Resumed { was_paused: true };
+        let json = serde_json::to_string(&response).unwrap();
+        assert_eq!(json, r#"{"result":"resumed","was_paused":true}"#);
+        assert_eq!(serde_json::from_str::<Response>(&json).unwrap(), response);
+
+        let error = Response::Error {
+            message: "nope".to_string(),
+        };
+        assert_eq!(error.into_result().unwrap_err().to_string(), "nope");
+    }
+}
@@ -0,0 +1,210 @@
+use anyhow::Result;
+use chrono::{DateTime, Local, TimeZone, Utc};
+
+use crate::config::{Config, FadeConfig};
+use crate::schedule::{next_transitions, Schedule, Transition};
+use crate::sun_times::ThemeMode;
+
+/// A point part-way through a gradual switch between light and dark
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fade {
+    pub from: ThemeMode,
+    pub from_phase: Option<String>,
+    pub to: ThemeMode,
+    pub to_phase: Option<String>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub step: u32,
+    pub steps: u32,
+}
+
+impl Fade {
+    /// How far the current step is from the old theme towards the new one
+    pub fn fraction(&self) -> f64 {
+        (self.step as f64 + 0.5) / self.steps as f64
+    }
+
+    /// When the next step should be shown
+    pub fn next_step_at(&self) -> DateTime<Utc> {
+        self.start + (self.end - self.start) * (self.step + 1) as i32 / self.steps as i32
+    }
+}
+
+/// The fade in progress at `now`, if fading is enabled and a switch
+/// between light and dark is near
+pub fn fade_at(config: &Config, now: DateTime<Utc>) -> Result<Option<Fade>> {
+    if !config.fade.enabled {
+        return Ok(None);
+    }
+
+    // Modes pinned by an exception change at once
+    if Schedule::today(config)?.exception_at(now).is_some() {
+        return Ok(None);
+    }
+
+    let from = now - config.fade.window() / 2;
+    let schedule = Schedule::for_date(config, from.with_timezone(&Local).date_naive())?;
+    let before = (
+        schedule.mode_at(from),
+        schedule.phase_at(from).map(|p| p.name.clone()),
+    );
+    let transitions = next_transitions(config, from, 4)?;
+    let exception_bounds: Vec<DateTime<Utc>> = config
+        .exceptions
+        .iter()
+        .flat_map(|e| [e.start, e.end])
+        .filter_map(|at| Local.from_local_datetime(&at).earliest())
+        .map(|at| at.with_timezone(&Utc))
+        .collect();
+    Ok(fade_in(
+        &config.fade,
+        now,
+        before,
+        &transitions,
+        &exception_bounds,
+    ))
+}
+
+/// Find the switch whose fade window contains `now`, given the state
+/// before the first of `transitions`. Switches where an exception starts
+/// or ends, at one of `exception_bounds`, happen at once.
+fn fade_in(
+    settings: &FadeConfig,
+    now: DateTime<Utc>,
+    mut before: (ThemeMode, Option<String>),
+    transitions: &[Transition],
+    exception_bounds: &[DateTime<Utc>],
+) -> Option<Fade> {
+    let half = settings.window() / 2;
+    for transition in transitions {
+        let start = transition.at - half;
+        let end = transition.at + half;
+        if start > now {
+            break;
+        }
+        // Phase changes that keep the same mode switch at once, and so do
+        // exceptions
+        if transition.mode != before.0 && now < end && !exception_bounds.contains(&transition.at) {
+            let steps = settings.steps();
+            let elapsed = (now - start).num_milliseconds();
+            let step = (elapsed * steps as i64 / (end - start).num_milliseconds()) as u32;
+            return Some(Fade {
+                from: before.0,
+                from_phase: before.1,
+                to: transition.mode,
+                to_phase: transition.phase.clone(),
+                start,
+                end,
+                step: step.min(steps - 1),
+                steps,
+            });
+        }
+        before = (transition.mode, transition.phase.clone());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn sunset() -> Transition {
+        Transition {
+            at: Utc.with_ymd_and_hms(2024, 3, 20, 18, 0, 0).unwrap(),
+            mode: ThemeMode::Dark,
+            phase: None,
+        }
+    }
+
+    fn at(h: u32, m: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 20, h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_fade_window_around_switch() {
+        let settings = FadeConfig {
+            enabled: true,
+            minutes: 40,
+            steps: 20,
+        };
+        let before = (ThemeMode::Light, None);
+        let transitions = [sunset()];
+
+        assert_eq!(
+            fade_in(&settings, at(17, 39), before.clone(), &transitions, &[]),
+            None
+        );
+        assert_eq!(
+            fade_in(&settings, at(18, 20), before.clone(), &transitions, &[]),
+            None
+        );
+
+        let first = fade_in(&settings, at(17, 40), before.clone(), &transitions, &[]).unwrap();
+        assert_eq!((first.from, first.to), (ThemeMode::Light, ThemeMode::Dark));
+        assert_eq!(first.step, 0);
+        assert_eq!(first.next_step_at(), at(17, 42));
+
+        let middle = fade_in(&settings, at(18, 0), before.clone(), &transitions, &[]).unwrap();
+        assert_eq!(middle.step, 10);
+        assert!(middle.fraction() > 0.5);
+
+        let last = fade_in(&settings, at(18, 19), before, &transitions, &[]).unwrap();
+        assert_eq!(last.step, 19);
+        assert_eq!(last.next_step_at(), at(18, 20));
+    }
+
+    #[test]
+    fn test_no_fade_between_phases_of_one_mode() {
+        let settings = FadeConfig {
+            enabled: true,
+            ..FadeConfig::default()
+        };
+        let golden = Transition {
+            at: at(17, 30),
+            mode: ThemeMode::Light,
+            phase: Some("golden".to_string()),
+        };
+        let before = (ThemeMode::Light, Some("day".to_string()));
+
+        assert_eq!(
+            fade_in(
+                &settings,
+                at(17, 30),
+                before.clone(),
+                &[golden.clone(), sunset()],
+                &[]
+            ),
+            None
+        );
+
+        let fade = fade_in(&settings, at(17, 50), before, &[golden, sunset()], &[]).unwrap();
+        assert_eq!(fade.from_phase.as_deref(), Some("golden"));
+        assert_eq!(fade.to, ThemeMode::Dark);
+    }
+
+    #[test]
+    fn test_no_fade_at_exception_bounds() {
+        let settings = FadeConfig {
+            enabled: true,
+            ..FadeConfig::default()
+        };
+        let before = (ThemeMode::Light, None);
+        let exception = [sunset().at];
+
+        assert_eq!(
+            fade_in(
+                &settings,
+                at(17, 55),
+                before.clone(),
+                &[sunset()],
+                &exception
+            ),
+            None
+        );
+        assert_eq!(
+            fade_in(&settings, at(18, 5), before, &[sunset()], &exception),
+            None
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::sun_times::ThemeMode;
+use crate::theme_switcher::Applied;
+
+/// What caused a switch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trigger {
+    /// The daemon following the schedule, phases or an exception
+    Scheduled,
+    /// `set` or `toggle`
+    Manual,
+    /// The daemon keeping an earlier `set` or `toggle` in force, e.g.
+    /// after a restart
+    Override,
+    /// Re-evaluating after a suspend, clock change or timezone change
+    Resume,
+    /// An ambient light reading crossing its threshold. suntheme has no
+    /// light sensor yet, so nothing records this, but readers accept it
+    Ambient,
+}
+
+impl std::fmt::Display for Trigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Trigger::Scheduled => "scheduled",
+            Trigger::Manual => "manual",
+            Trigger::Override => "override",
+            Trigger::Resume => "resume",
+            Trigger::Ambient => "ambient",
+        };
+        f.pad(name)
+    }
+}
+
+/// A target that failed to switch, and why
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetError {
+    pub target: String,
+    pub error: String,
+}
+
+/// One line of `history.jsonl`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub at: DateTime<Utc>,
+    pub mode: ThemeMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phase: Option<String>,
+    pub trigger: Trigger,
+    /// The targets that switched
+    pub targets: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<TargetError>,
+}
+
+impl HistoryEntry {
+    pub fn new(
+        at: DateTime<Utc>,
+        mode: ThemeMode,
+        phase: Option<&str>,
+        trigger: Trigger,
+        applied: &Applied,
+    ) -> Self {
+        Self {
+            at,
+            mode,
+            phase: phase.map(String::from),
+            trigger,
+            targets: applied.succeeded.iter().map(|t| t.to_string()).collect(),
+            errors: applied
+                .failed
+                .iter()
+                .map(|(target, e)| TargetError {
+                    target: target.to_string(),
+                    error: format!("{:#}", e),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn at_local(&self) -> DateTime<Local> {
+        self.at.with_timezone(&Local)
+    }
+}
+
+/// Append a switch to the history file
+pub fn record(entry: &HistoryEntry) -> Result<()> {
+    append(&Config::history_file()?, entry)
+}
+
+fn append(path: &Path, entry: &HistoryEntry) -> Result<()> {
+    fs::create_dir_all(path.parent().unwrap())?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {:?}", path))?;
+    // One write per line, so concurrent writers can't interleave
+    let line = format!("{}\n", serde_json::to_string(entry)?);
+    file.write_all(line.as_bytes())
+        .with_context(|| format!("Failed to write {:?}", path))?;
+    Ok(())
+}
+
+/// The switches since `since`, oldest first. A missing file is an empty
+/// history; lines that don't parse are skipped.
+pub fn since(since: DateTime<Utc>) -> Result<Vec<HistoryEntry>> {
+    read_since(&Config::history_file()?, since)
+}
+
+fn read_since(path: &Path, since: DateTime<Utc>) -> Result<Vec<HistoryEntry>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+    };
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<HistoryEntry>(line).ok())
+        .filter(|entry| entry.at >= since)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_append_and_read_since() {
+        let path = std::env::temp_dir().join(format!("suntheme-history-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        assert!(read_since(&path, Utc::now()).unwrap().is_empty());
+
+        let applied = Applied {
+            succeeded: vec!["ghostty"],
+            failed: vec![("neovim", anyhow::anyhow!("Permission denied"))],
+        };
+        let morning = Utc.with_ymd_and_hms(2024, 6, 10, 7, 0, 0).unwrap();
+        let evening = Utc.with_ymd_and_hms(2024, 6, 10, 19, 0, 0).unwrap();
+        let light = HistoryEntry::new(
+            morning,
+            ThemeMode::Light,
+            None,
+            Trigger::Scheduled,
+            &applied,
+        );
+        let dark = HistoryEntry::new(
+            evening,
+            ThemeMode::Dark,
+            Some("night"),
+            Trigger::Manual,
+            &Applied::default(),
+        );
+        append(&path, &light).unwrap();
+        fs::write(&path, fs::read_to_string(&path).unwrap() + "not json\n").unwrap();
+        append(&path, &dark).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with(
+            r#"{"at":"2024-06-10T07:00:00Z","mode":"light","trigger":"scheduled","targets":["ghostty"],"errors":[{"target":"neovim","error":"Permission denied"}]}"#
+        ));
+
+        assert_eq!(
+            read_since(&path, morning).unwrap(),
+            vec![light, dark.clone()]
+        );
+        assert_eq!(read_since(&path, evening).unwrap(), vec![dark]);
+
+        let ambient: HistoryEntry = serde_json::from_str(
+            r#"{"at":"2024-06-10T20:00:00Z","mode":"dark","trigger":"ambient","targets":[],"errors":[]}"#,
+        )
+        .unwrap();
+        assert_eq!(ambient.trigger, Trigger::Ambient);
+        assert_eq!(ambient.trigger.to_string(), "ambient");
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,203 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+
+/// How long to wait out another process's moment with the lock file:
+/// `holder` taking the lock shared to see whether a daemon is running, or
+/// a starting daemon that has the lock but hasn't written its PID yet
+const LOCK_PATIENCE: Duration = Duration::from_millis(250);
+
+/// An advisory lock on `daemon.lock`, held for as long as the daemon runs.
+/// The kernel drops it when the process exits, however that happens, so
+/// unlike a PID file it can't go stale. The file also records the PID.
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Take the lock for this process, failing if another daemon holds it
+    pub fn acquire() -> Result<Self> {
+        Self::acquire_at(&Config::lock_file()?)
+    }
+
+    fn acquire_at(path: &Path) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("Failed to open lock file {:?}", path))?;
+
+        let deadline = Instant::now() + LOCK_PATIENCE;
+        while !try_lock(&file, libc::LOCK_EX)? {
+            if Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+            match read_pid(&mut file) {
+                Some(pid) => anyhow::bail!("The daemon is already running (PID: {}).", pid),
+                None => anyhow::bail!("The daemon is already running."),
+            }
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{}", std::process::id())?;
+        Ok(Self { _file: file })
+    }
+
+    /// The PID of the daemon holding the lock, or `None` if no daemon does
+    pub fn holder() -> Result<Option<i32>> {
+        Self::holder_at(&Config::lock_file()?)
+    }
+
+    fn holder_at(path: &Path) -> Result<Option<i32>> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to open {:?}", path)),
+        };
+
+        let deadline = Instant::now() + LOCK_PATIENCE;
+        loop {
+            // Getting a shared lock means nobody holds the exclusive one;
+            // ours goes away when `file` is closed, and a daemon starting
+            // meanwhile waits for that
+            if try_lock(&file, libc::LOCK_SH)? {
+                return Ok(None);
+            }
+            if let Some(pid) = read_pid(&mut file) {
+                return Ok(Some(pid));
+            }
+            if Instant::now() >= deadline {
+                anyhow::bail!("The daemon holds its lock but hasn't written its PID");
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+/// Try to take a lock without waiting, returning whether it was free
+fn try_lock(file: &File, operation: libc::c_int) -> Result<bool> {
+    if unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    if err.raw_os_error() == Some(libc::EWOULDBLOCK) {
+        return Ok(false);
+    }
+    Err(err).context("Failed to lock the daemon lock file")
+}
+
+fn read_pid(file: &mut File) -> Option<i32> {
+    let mut content = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut content).ok()?;
+    content.trim().parse().ok()
+}
+
+/// Whether `pid` is a running suntheme process, so it's safe to signal.
+/// Checks the executable on Linux and the command name elsewhere.
+pub fn is_suntheme(pid: i32) -> bool {
+    let proc_dir = Path::new("/proc").join(pid.to_string());
+    if proc_dir.exists() {
+        // After an upgrade the link ends in " (deleted)"
+        if let Ok(exe) = fs::read_link(proc_dir.join("exe")) {
+            return is_suntheme_path(&exe.to_string_lossy());
+        }
+        // Not our process to inspect; the command line is still readable
+        return fs::read(proc_dir.join("cmdline")).is_ok_and(|cmdline| {
+            let argv0 = cmdline.split(|b| *b == 0).next().unwrap_or_default();
+            is_suntheme_path(&String::from_utf8_lossy(argv0))
+        });
+    }
+
+    std::process::Command::new("ps")
+        .args(["-p", &pid.to_string(), "-o", "comm="])
+        .output()
+        .is_ok_and(|output| {
+            output.status.success()
+                && is_suntheme_path(String::from_utf8_lossy(&output.stdout).trim())
+        })
+}
+
+fn is_suntheme_path(path: &str) -> bool {
+    Path::new(path)
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with("suntheme"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let path = std::env::temp_dir().join(format!("suntheme-lock-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        assert_eq!(InstanceLock::holder_at(&path).unwrap(), None);
+
+        let lock = InstanceLock::acquire_at(&path).unwrap();
+        let pid = std::process::id() as i32;
+        assert_eq!(InstanceLock::holder_at(&path).unwrap(), Some(pid));
+        let err = InstanceLock::acquire_at(&path).err().unwrap();
+        assert!(err.to_string().contains(&format!("PID: {}", pid)));
+
+        drop(lock);
+        assert_eq!(InstanceLock::holder_at(&path).unwrap(), None);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_holder_waits_for_the_pid() {
+        let path = std::env::temp_dir().join(format!("suntheme-nopid-{}", std::process::id()));
+        fs::write(&path, "").unwrap();
+
+        // A daemon that has just taken the lock
+        let mut starting = OpenOptions::new().write(true).open(&path).unwrap();
+        assert!(try_lock(&starting, libc::LOCK_EX).unwrap());
+        let written = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            writeln!(starting, "4242").unwrap();
+            starting
+        });
+
+        assert_eq!(InstanceLock::holder_at(&path).unwrap(), Some(4242));
+        drop(written.join().unwrap());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_acquire_waits_out_a_probe() {
+        let path = std::env::temp_dir().join(format!("suntheme-probe-{}", std::process::id()));
+        fs::write(&path, "").unwrap();
+
+        // Someone checking `holder` at the moment the daemon starts
+        let probe = File::open(&path).unwrap();
+        assert!(try_lock(&probe, libc::LOCK_SH).unwrap());
+        let released = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            drop(probe);
+        });
+
+        let lock = InstanceLock::acquire_at(&path).unwrap();
+        released.join().unwrap();
+        drop(lock);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_is_suntheme() {
+        // Test binaries are named suntheme-<hash>
+        assert!(is_suntheme(std::process::id() as i32));
+        assert!(is_suntheme_path("/usr/local/bin/suntheme (deleted)"));
+        assert!(!is_suntheme_path("/usr/bin/sleep"));
+        assert!(!is_suntheme_path("/opt/not-suntheme"));
+    }
+}
@@ -0,0 +1,390 @@
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDateTime, SecondsFormat, TimeZone};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use crate::config::{Config, LogConfig, LogFormat};
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Which records to keep, in `RUST_LOG` syntax: comma-separated `level` or
+/// `target=level` directives, where a bare target means every level. The
+/// longest matching target wins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Filter {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut default = None;
+        let mut targets = Vec::new();
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    let level = level.trim().parse().map_err(|_| {
+                        anyhow::anyhow!(
+                            "Invalid log level in '{}'. Use off, error, warn, info, debug or trace.",
+                            directive
+                        )
+                    })?;
+                    targets.push((target.trim().to_string(), level));
+                }
+                None => match directive.parse() {
+                    Ok(level) => default = Some(level),
+                    Err(_) => targets.push((directive.to_string(), LevelFilter::Trace)),
+                },
+            }
+        }
+        targets.sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+
+        // Naming only some targets silences the rest, as with env_logger
+        let default = default.unwrap_or(if targets.is_empty() {
+            LevelFilter::Info
+        } else {
+            LevelFilter::Off
+        });
+        Ok(Self { default, targets })
+    }
+
+    pub fn enabled(&self, target: &str, level: Level) -> bool {
+        let max = self
+            .targets
+            .iter()
+            .find(|(prefix, _)| {
+                target == prefix
+                    || target
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .map_or(self.default, |(_, level)| *level);
+        level <= max
+    }
+
+    /// The most verbose level any target allows
+    fn max_level(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, std::cmp::max)
+    }
+}
+
+struct Settings {
+    filter: Filter,
+    format: LogFormat,
+    /// Set when stderr is `daemon.log`, which is then rotated when full
+    rotation: Option<Rotation>,
+}
+
+struct Rotation {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: u32,
+}
+
+/// Writes log records to stderr, which is `daemon.log` for a daemon
+/// started with `suntheme start` or by launchd, and the journal under
+/// systemd
+struct Logger {
+    settings: Mutex<Option<Settings>>,
+}
+
+static LOGGER: Logger = Logger {
+    settings: Mutex::new(None),
+};
+
+/// Set up logging for the daemon from its config, or update it after a
+/// reload. `RUST_LOG` takes precedence over the configured level.
+pub fn init(config: &LogConfig) {
+    let spec = std::env::var("RUST_LOG")
+        .ok()
+        .filter(|spec| !spec.trim().is_empty())
+        .unwrap_or_else(|| config.level.clone());
+    let (filter, invalid) = match Filter::parse(&spec) {
+        Ok(filter) => (filter, None),
+        Err(e) => (Filter::parse("info").unwrap(), Some(e)),
+    };
+
+    let rotation = Config::log_file()
+        .ok()
+        .filter(|path| stderr_is(path))
+        .map(|path| Rotation {
+            path,
+            max_bytes: config.max_size_kb.max(1) * 1024,
+            keep: config.keep,
+        });
+
+    log::set_max_level(filter.max_level());
+    *LOGGER
+        .settings
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = Some(Settings {
+        filter,
+        format: config.format,
+        rotation,
+    });
+    // Only fails when already set, on a reload
+    let _ = log::set_logger(&LOGGER);
+
+    if let Some(e) = invalid {
+        log::warn!("{:#}, logging at info", e);
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let settings = self.settings.lock().unwrap_or_else(PoisonError::into_inner);
+        settings
+            .as_ref()
+            .is_some_and(|s| s.filter.enabled(metadata.target(), metadata.level()))
+    }
+
+    fn log(&self, record: &Record) {
+        let settings = self.settings.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(settings) = settings.as_ref() else {
+            return;
+        };
+        if !settings.filter.enabled(record.target(), record.level()) {
+            return;
+        }
+
+        let line = format_record(
+            settings.format,
+            Local::now(),
+            record.level(),
+            record.target(),
+            &record.args().to_string(),
+        );
+        if let Some(rotation) = &settings.rotation {
+            if let Err(e) = rotation.rotate_if_full(line.len() as u64) {
+                let _ = writeln!(io::stderr(), "Failed to rotate the log: {}", e);
+            }
+        }
+        let _ = io::stderr().write_all(line.as_bytes());
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+/// A log line in the JSON format, with fields in this order
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    time: String,
+    level: String,
+    target: &'a str,
+    message: &'a str,
+}
+
+/// One log line, ending in a newline
+fn format_record(
+    format: LogFormat,
+    time: DateTime<Local>,
+    level: Level,
+    target: &str,
+    message: &str,
+) -> String {
+    match format {
+        LogFormat::Text => format!("[{}] {:<5} {}\n", time.format(TIME_FORMAT), level, message),
+        LogFormat::Json => {
+            let record = JsonRecord {
+                time: time.to_rfc3339_opts(SecondsFormat::Millis, false),
+                level: level.as_str().to_lowercase(),
+                target,
+                message,
+            };
+            let mut line = serde_json::to_string(&record).unwrap_or_default();
+            line.push('\n');
+            line
+        }
+    }
+}
+
+/// The time a log line was written, in either format. Lines from before
+/// structured logging have the same `[time]` prefix as text lines.
+pub fn line_time(line: &str) -> Option<DateTime<Local>> {
+    if line.starts_with('{') {
+        let value: serde_json::Value = serde_json::from_str(line).ok()?;
+        let time = DateTime::parse_from_rfc3339(value.get("time")?.as_str()?).ok()?;
+        return Some(time.with_timezone(&Local));
+    }
+    let (time, _) = line.strip_prefix('[')?.split_once(']')?;
+    let time = NaiveDateTime::parse_from_str(time, TIME_FORMAT).ok()?;
+    Local.from_local_datetime(&time).earliest()
+}
+
+impl Rotation {
+    /// Rotate before writing `len` more bytes would take the log past its
+    /// limit, then point stdout and stderr at the fresh file so panics and
+    /// stray output follow
+    fn rotate_if_full(&self, len: u64) -> io::Result<()> {
+        let size = fstat(libc::STDERR_FILENO)?.st_size as u64;
+        if size == 0 || size + len <= self.max_bytes || !stderr_is(&self.path) {
+            return Ok(());
+        }
+
+        rotate(&self.path, self.keep)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+            if unsafe { libc::dup2(file.as_raw_fd(), fd) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Shift `daemon.log` to `daemon.log.1`, `.1` to `.2` and so on, dropping
+/// logs past `keep`
+fn rotate(path: &Path, keep: u32) -> io::Result<()> {
+    if keep == 0 {
+        return fs::remove_file(path);
+    }
+    let _ = fs::remove_file(numbered(path, keep));
+    for n in (1..keep).rev() {
+        let from = numbered(path, n);
+        if from.exists() {
+            fs::rename(from, numbered(path, n + 1))?;
+        }
+    }
+    fs::rename(path, numbered(path, 1))
+}
+
+fn numbered(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// `path` and its rotated logs that exist, oldest first
+pub fn log_files(path: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = (1..)
+        .map(|n| numbered(path, n))
+        .take_while(|file| file.exists())
+        .collect();
+    files.reverse();
+    if path.exists() {
+        files.push(path.to_path_buf());
+    }
+    files
+}
+
+/// Whether stderr is the file at `path`
+// `st_dev` and `st_ino` are u64 on Linux but not everywhere
+#[allow(clippy::unnecessary_cast)]
+fn stderr_is(path: &Path) -> bool {
+    match (fstat(libc::STDERR_FILENO), fs::metadata(path)) {
+        (Ok(stderr), Ok(file)) => {
+            stderr.st_dev as u64 == file.dev() && stderr.st_ino as u64 == file.ino()
+        }
+        _ => false,
+    }
+}
+
+fn fstat(fd: RawFd) -> io::Result<libc::stat> {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let filter = Filter::parse("warn,suntheme::schedule=debug,suntheme::sun").unwrap();
+        assert!(filter.enabled("suntheme::commands::daemon", Level::Warn));
+        assert!(!filter.enabled("suntheme::commands::daemon", Level::Info));
+        assert!(filter.enabled("suntheme::schedule", Level::Debug));
+        assert!(!filter.enabled("suntheme::schedule", Level::Trace));
+        // Targets match whole path segments only
+        assert!(filter.enabled("suntheme::sun", Level::Trace));
+        assert!(!filter.enabled("suntheme::sun_times", Level::Info));
+        assert_eq!(filter.max_level(), LevelFilter::Trace);
+
+        let filter = Filter::parse("suntheme=debug").unwrap();
+        assert!(filter.enabled("suntheme::control", Level::Debug));
+        assert!(!filter.enabled("reqwest::connect", Level::Error));
+
+        assert!(Filter::parse("").unwrap().enabled("suntheme", Level::Info));
+        assert!(Filter::parse("suntheme=loud").is_err());
+    }
+
+    #[test]
+    fn test_format_record() {
+        let time = Local.with_ymd_and_hms(2024, 6, 10, 18, 0, 0).unwrap();
+        let text = format_record(
+            LogFormat::Text,
+            time,
+            Level::Info,
+            "suntheme::commands::daemon",
+            "Current mode: dark",
+        );
+        assert_eq!(text, "[2024-06-10 18:00:00] INFO  Current mode: dark\n");
+        assert_eq!(line_time(&text), Some(time));
+
+        let json = format_record(
+            LogFormat::Json,
+            time,
+            Level::Warn,
+            "suntheme::control",
+            "Failed to answer \"status\"",
+        );
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["level"], "warn");
+        assert_eq!(value["target"], "suntheme::control");
+        assert_eq!(value["message"], "Failed to answer \"status\"");
+        assert_eq!(line_time(&json), Some(time));
+
+        assert_eq!(line_time("thread 'main' panicked"), None);
+    }
+
+    #[test]
+    fn test_rotate() {
+        let dir = std::env::temp_dir().join(format!("suntheme-rotate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("daemon.log");
+
+        for content in ["one", "two", "three", "four"] {
+            fs::write(&path, content).unwrap();
+            rotate(&path, 2).unwrap();
+        }
+        assert!(!path.exists());
+        assert_eq!(
+            fs::read_to_string(dir.join("daemon.log.1")).unwrap(),
+            "four"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("daemon.log.2")).unwrap(),
+            "three"
+        );
+        assert!(!dir.join("daemon.log.3").exists());
+
+        fs::write(&path, "five").unwrap();
+        assert_eq!(
+            log_files(&path),
+            [
+                dir.join("daemon.log.2"),
+                dir.join("daemon.log.1"),
+                path.clone()
+            ]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
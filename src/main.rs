@@ -1,13 +1,29 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 
+mod backoff;
 mod banner;
 mod commands;
 mod config;
+mod control;
+mod fade;
+mod history;
+mod instance;
+mod logging;
+mod manual;
+mod notify;
+mod palette;
+mod resume;
+mod schedule;
+mod service;
+mod solar;
+mod stability;
+mod sun_cache;
 mod sun_times;
 mod telemetry;
 mod theme_switcher;
 mod themes;
+mod watch;
 
 use sun_times::ThemeMode;
 
@@ -28,26 +44,169 @@ enum Commands {
     /// Start the daemon for automatic theme switching
     Start,
 
+    /// Run the daemon, or with --foreground keep it attached for a supervisor
+    Run {
+        /// Stay in the foreground, log to stderr and skip the PID file
+        #[arg(long)]
+        foreground: bool,
+    },
+
     /// Stop the running daemon
     Stop,
 
+    /// Stop the daemon if it's running and start it again
+    Restart,
+
     /// Show daemon status and current theme
     Status,
 
+    /// Make the running daemon re-read its config now
+    Reload,
+
+    /// Show the daemon log
+    Logs {
+        /// Keep printing new lines as they are written
+        #[arg(short, long)]
+        follow: bool,
+
+        /// Show entries since a time ago, e.g. 2h, or a local time such as
+        /// "2024-06-10 09:00", including rotated logs
+        #[arg(long, value_name = "TIME")]
+        since: Option<String>,
+
+        /// Number of lines to show when --since is not given
+        #[arg(short = 'n', long, default_value_t = 50)]
+        lines: usize,
+    },
+
+    /// Show when the theme switched, what caused it and which targets
+    /// followed
+    History {
+        /// Number of days back to show
+        #[arg(short, long, default_value_t = 7)]
+        days: u32,
+
+        /// Print the entries as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
     /// Toggle between light and dark themes
-    Toggle,
+    Toggle {
+        #[command(flatten)]
+        expiry: ExpiryArgs,
+    },
 
     /// Set a specific theme mode
     Set {
         /// Theme mode: light or dark
         mode: ThemeMode,
+        #[command(flatten)]
+        expiry: ExpiryArgs,
+    },
+
+    /// Pause automatic switching without stopping the daemon
+    Pause {
+        /// How long to pause, e.g. 1h or 90m. Pauses until resumed if omitted.
+        #[arg(long = "for", value_name = "DURATION")]
+        duration: Option<String>,
     },
 
+    /// Resume automatic switching after a pause
+    Resume,
+
     /// Display today's sunrise and sunset times
-    Sun,
+    Sun {
+        /// Number of upcoming switches to list
+        #[arg(short, long, default_value_t = 1)]
+        next: usize,
+    },
+
+    /// Show the switching plan for the coming week
+    Schedule {
+        /// Number of days to show
+        #[arg(short, long, default_value_t = 7)]
+        days: usize,
+    },
 
     /// Configure theme names for Ghostty and Neovim
     Themes,
+
+    /// Inspect or clear the sun times cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+
+    /// Pin a mode for a date range, overriding the schedule
+    Except {
+        #[command(subcommand)]
+        action: ExceptAction,
+    },
+
+    /// Run the daemon at login as a systemd user service or launchd agent
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
+    },
+}
+
+/// How long a manual choice holds before automatic switching resumes.
+/// Defaults to the next natural switch.
+#[derive(Args)]
+struct ExpiryArgs {
+    /// Keep the mode until the `next` switch or a local time (HH:MM)
+    #[arg(long, conflicts_with = "duration")]
+    until: Option<String>,
+
+    /// Keep the mode for a duration, e.g. 2h or 90m
+    #[arg(long = "for", id = "duration", value_name = "DURATION")]
+    duration: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// List cached sun times
+    Show,
+
+    /// Remove all cached sun times
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum ExceptAction {
+    /// Add an exception
+    Add {
+        /// Theme mode to hold: light or dark
+        mode: ThemeMode,
+        /// Start, as YYYY-MM-DD or "YYYY-MM-DD HH:MM"
+        #[arg(long)]
+        start: String,
+        /// End, as YYYY-MM-DD (inclusive) or "YYYY-MM-DD HH:MM"
+        #[arg(long)]
+        end: String,
+        /// Optional description
+        #[arg(long)]
+        note: Option<String>,
+    },
+
+    /// List exceptions
+    List,
+
+    /// Remove an exception by its number in the list
+    Remove { index: usize },
+}
+
+#[derive(Subcommand)]
+enum ServiceAction {
+    /// Write and enable the systemd unit or launchd agent
+    Install,
+
+    /// Disable and remove the service
+    Uninstall,
+
+    /// Show whether the service is installed and running
+    Status,
 }
 
 fn main() -> Result<()> {
@@ -56,11 +215,47 @@ fn main() -> Result<()> {
     match cli.command {
         Commands::Init => commands::init::run(),
         Commands::Start => commands::daemon::start(),
+        Commands::Run { foreground: true } => commands::daemon::run_foreground(),
+        Commands::Run { foreground: false } => commands::daemon::start(),
         Commands::Stop => commands::daemon::stop(),
+        Commands::Restart => commands::daemon::restart(),
         Commands::Status => commands::daemon::status(),
-        Commands::Toggle => commands::theme::toggle(),
-        Commands::Set { mode } => commands::theme::set(mode),
-        Commands::Sun => commands::sun::run(),
+        Commands::Reload => commands::daemon::reload(),
+        Commands::Logs {
+            follow,
+            since,
+            lines,
+        } => commands::logs::run(follow, since.as_deref(), lines),
+        Commands::History { days, json } => commands::history::run(days, json),
+        Commands::Toggle { expiry } => {
+            commands::theme::toggle(expiry.until.as_deref(), expiry.duration.as_deref())
+        }
+        Commands::Set { mode, expiry } => {
+            commands::theme::set(mode, expiry.until.as_deref(), expiry.duration.as_deref())
+        }
+        Commands::Pause { duration } => commands::pause::pause(duration.as_deref()),
+        Commands::Resume => commands::pause::resume(),
+        Commands::Sun { next } => commands::sun::run(next),
+        Commands::Schedule { days } => commands::schedule::run(days),
         Commands::Themes => commands::theme::configure_themes(),
+        Commands::Cache { action } => match action {
+            CacheAction::Show => commands::cache::show(),
+            CacheAction::Clear => commands::cache::clear(),
+        },
+        Commands::Except { action } => match action {
+            ExceptAction::Add {
+                mode,
+                start,
+                end,
+                note,
+            } => commands::except::add(mode, &start, &end, note),
+            ExceptAction::List => commands::except::list(),
+            ExceptAction::Remove { index } => commands::except::remove(index),
+        },
+        Commands::Service { action } => match action {
+            ServiceAction::Install => commands::service::install(),
+            ServiceAction::Uninstall => commands::service::uninstall(),
+            ServiceAction::Status => commands::service::status(),
+        },
     }
 }
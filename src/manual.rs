@@ -0,0 +1,292 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Local, NaiveTime, TimeZone, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::history::{self, HistoryEntry, Trigger};
+use crate::schedule::{next_transitions, SEARCH_DAYS};
+use crate::sun_times::ThemeMode;
+use crate::theme_switcher::ThemeSwitcher;
+
+/// A mode chosen with `set` or `toggle`, which the daemon keeps until it
+/// expires
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Override {
+    pub mode: ThemeMode,
+    pub until: DateTime<Utc>,
+    /// When the choice was made
+    #[serde(default)]
+    pub since: DateTime<Utc>,
+}
+
+impl Override {
+    /// Load the saved override, treating a missing or unreadable file as none
+    pub fn load() -> Option<Self> {
+        load_json(&Config::override_file().ok()?)
+    }
+
+    /// The override in force at `now`, if any
+    pub fn active(now: DateTime<Utc>) -> Option<Self> {
+        Self::load().filter(|o| o.until > now)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        save_json(&Config::override_file()?, self)
+    }
+
+    pub fn clear() -> Result<bool> {
+        remove_file(&Config::override_file()?)
+    }
+
+    pub fn until_local(&self) -> DateTime<Local> {
+        self.until.with_timezone(&Local)
+    }
+}
+
+/// Apply `mode` and save an override holding it from `now` until the
+/// expiry chosen with `--until` or `--for`. The switch goes in the history
+/// either way; the override is only saved once the theme is applied.
+pub fn hold(
+    config: &Config,
+    mode: ThemeMode,
+    now: DateTime<Utc>,
+    until: Option<&str>,
+    duration: Option<&str>,
+) -> Result<Override> {
+    let until = expiry(config, now, until, duration)?;
+
+    let applied = ThemeSwitcher::new(config.clone()).apply_targets(mode, None);
+    let entry = HistoryEntry::new(now, mode, None, Trigger::Manual, &applied);
+    if let Err(e) = history::record(&entry) {
+        log::warn!("Failed to record switch: {:#}", e);
+    }
+    if let Some(error) = applied.error() {
+        anyhow::bail!(error);
+    }
+
+    let manual = Override {
+        mode,
+        until,
+        since: now,
+    };
+    manual.save()?;
+    Ok(manual)
+}
+
+/// Automatic switching suspended with `pause`, e.g. while screen sharing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pause {
+    pub since: DateTime<Utc>,
+    /// `None` pauses until `resume`
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl Pause {
+    /// Pause from `now`, for `duration` (e.g. `1h`) or until resumed
+    pub fn start(now: DateTime<Utc>, duration: Option<&str>) -> Result<Self> {
+        let until = duration
+            .map(|duration| after(now, parse_duration(duration)?))
+            .transpose()?;
+        let pause = Pause { since: now, until };
+        pause.save()?;
+        Ok(pause)
+    }
+
+    /// Load the saved pause, treating a missing or unreadable file as none
+    pub fn load() -> Option<Self> {
+        load_json(&Config::pause_file().ok()?)
+    }
+
+    /// The pause in force at `now`, if any
+    pub fn active(now: DateTime<Utc>) -> Option<Self> {
+        Self::load().filter(|p| p.until.is_none_or(|until| until > now))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        save_json(&Config::pause_file()?, self)
+    }
+
+    pub fn clear() -> Result<bool> {
+        remove_file(&Config::pause_file()?)
+    }
+
+    pub fn until_local(&self) -> Option<DateTime<Local>> {
+        self.until.map(|t| t.with_timezone(&Local))
+    }
+}
+
+fn load_json<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, serde_json::to_string_pretty(value)?)
+        .with_context(|| format!("Failed to write {:?}", path))?;
+    Ok(())
+}
+
+/// Remove a state file, returning whether it existed
+fn remove_file(path: &Path) -> Result<bool> {
+    if !path.exists() {
+        return Ok(false);
+    }
+    fs::remove_file(path).with_context(|| format!("Failed to remove {:?}", path))?;
+    Ok(true)
+}
+
+/// How long a manual choice lasts by default when the next natural switch
+/// can't be worked out, e.g. without a location
+const FALLBACK_HOLD_HOURS: i64 = 12;
+
+/// Work out when a manual choice made at `now` should expire, from
+/// `--until` (`next` or a local `HH:MM`) or `--for` (e.g. `2h`). Without
+/// either it lasts until the next natural switch, or for
+/// `FALLBACK_HOLD_HOURS` if that's unknown.
+pub fn expiry(
+    config: &Config,
+    now: DateTime<Utc>,
+    until: Option<&str>,
+    duration: Option<&str>,
+) -> Result<DateTime<Utc>> {
+    if let Some(duration) = duration {
+        return after(now, parse_duration(duration)?);
+    }
+
+    match until {
+        None | Some("next") => Ok(match next_transitions(config, now, 1) {
+            Ok(next) => next
+                .first()
+                .map(|t| t.at)
+                .unwrap_or(now + Duration::days(SEARCH_DAYS)),
+            Err(_) => now + Duration::hours(FALLBACK_HOLD_HOURS),
+        }),
+        Some(time) => {
+            let time = NaiveTime::parse_from_str(time.trim(), "%H:%M").with_context(|| {
+                format!("Invalid time: {}. Use 'next' or HH:MM, e.g. 17:00.", time)
+            })?;
+            next_clock_time(&Local, now, time)
+        }
+    }
+}
+
+/// The next time the local clock reads `time` after `now`
+fn next_clock_time<Tz: TimeZone>(
+    tz: &Tz,
+    now: DateTime<Utc>,
+    time: NaiveTime,
+) -> Result<DateTime<Utc>> {
+    let today = now.with_timezone(tz).date_naive();
+    (0..2)
+        .filter_map(|offset| {
+            tz.from_local_datetime(&(today + Duration::days(offset)).and_time(time))
+                .earliest()
+        })
+        .map(|t| t.with_timezone(&Utc))
+        .find(|t| *t > now)
+        .context("Could not resolve that time in the local timezone")
+}
+
+/// `now` plus `duration`, as an error rather than a panic when that's past
+/// the end of time
+fn after(now: DateTime<Utc>, duration: Duration) -> Result<DateTime<Utc>> {
+    now.checked_add_signed(duration)
+        .context("Invalid duration: duration too long")
+}
+
+/// Parse a duration such as `90m`, `2h` or `1h30m`
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let invalid = || anyhow::anyhow!("Invalid duration: {}. Use e.g. 30m, 2h or 1h30m.", s);
+    let too_long = || anyhow::anyhow!("Invalid duration: {}: duration too long", s);
+
+    let mut total = Duration::zero();
+    let mut digits = String::new();
+    for c in s.trim().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+
+        let value: i64 = digits.parse().map_err(|_| invalid())?;
+        digits.clear();
+        let part = match c.to_ascii_lowercase() {
+            'd' => Duration::try_days(value),
+            'h' => Duration::try_hours(value),
+            'm' => Duration::try_minutes(value),
+            's' => Duration::try_seconds(value),
+            _ => return Err(invalid()),
+        };
+        total = part
+            .and_then(|part| total.checked_add(&part))
+            .ok_or_else(too_long)?;
+    }
+
+    if !digits.is_empty() || total <= Duration::zero() {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("2h").unwrap(), Duration::hours(2));
+        assert_eq!(parse_duration("90m").unwrap(), Duration::minutes(90));
+        assert_eq!(
+            parse_duration("1h30m").unwrap(),
+            Duration::hours(1) + Duration::minutes(30)
+        );
+        assert_eq!(parse_duration("1d").unwrap(), Duration::days(1));
+        assert!(parse_duration("2").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("2x").is_err());
+        assert!(parse_duration("0m").is_err());
+    }
+
+    #[test]
+    fn test_overflowing_duration() {
+        let err = parse_duration("999999999999999d").unwrap_err();
+        assert!(err.to_string().contains("duration too long"));
+        let err = parse_duration("99999999999999999999m").unwrap_err();
+        assert!(err.to_string().contains("Invalid duration"));
+
+        // Parses, but lands past the end of time
+        let now = Utc::now();
+        let err = expiry(&Config::default(), now, None, Some("200000000d")).unwrap_err();
+        assert!(err.to_string().contains("duration too long"));
+    }
+
+    #[test]
+    fn test_expiry_without_a_schedule() {
+        // Sun mode without a location has no next switch to wait for
+        let now = Utc::now();
+        assert_eq!(
+            expiry(&Config::default(), now, None, None).unwrap(),
+            now + Duration::hours(FALLBACK_HOLD_HOURS)
+        );
+    }
+
+    #[test]
+    fn test_next_clock_time() {
+        let time = NaiveTime::from_hms_opt(17, 0, 0).unwrap();
+
+        let morning = Utc.with_ymd_and_hms(2024, 6, 10, 9, 0, 0).unwrap();
+        assert_eq!(
+            next_clock_time(&Utc, morning, time).unwrap(),
+            Utc.with_ymd_and_hms(2024, 6, 10, 17, 0, 0).unwrap()
+        );
+
+        let evening = Utc.with_ymd_and_hms(2024, 6, 10, 18, 0, 0).unwrap();
+        assert_eq!(
+            next_clock_time(&Utc, evening, time).unwrap(),
+            Utc.with_ymd_and_hms(2024, 6, 11, 17, 0, 0).unwrap()
+        );
+    }
+}
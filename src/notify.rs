@@ -0,0 +1,74 @@
+use std::ffi::OsStr;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::UnixDatagram;
+
+/// Tell systemd the daemon has started, along with a status line
+pub fn ready(status: &str) {
+    notify(&format!("READY=1\nSTATUS={}", status));
+}
+
+/// Update the status line shown by `systemctl --user status`
+pub fn status(status: &str) {
+    notify(&format!("STATUS={}", status));
+}
+
+pub fn stopping() {
+    notify("STOPPING=1");
+}
+
+/// Send `message` to `$NOTIFY_SOCKET` when running as a systemd
+/// `Type=notify` service. Does nothing otherwise.
+fn notify(message: &str) {
+    let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = send(&socket, message) {
+        log::warn!("Failed to notify systemd: {}", e);
+    }
+}
+
+fn send(socket: &OsStr, message: &str) -> io::Result<()> {
+    let datagram = UnixDatagram::unbound()?;
+
+    // A leading @ names a socket in the abstract namespace
+    if let Some(name) = socket.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            datagram.send_to_addr(message.as_bytes(), &addr)?;
+            return Ok(());
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = name;
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract sockets are only supported on Linux",
+            ));
+        }
+    }
+
+    datagram.send_to(message.as_bytes(), socket)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send_to_socket_path() {
+        let path = std::env::temp_dir().join(format!("suntheme-notify-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixDatagram::bind(&path).unwrap();
+
+        send(path.as_os_str(), "READY=1\nSTATUS=light").unwrap();
+        let mut buf = [0; 64];
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1\nSTATUS=light");
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,247 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+
+/// Theme keys holding a single colour
+const COLOR_KEYS: [&str; 6] = [
+    "background",
+    "foreground",
+    "cursor-color",
+    "cursor-text",
+    "selection-background",
+    "selection-foreground",
+];
+
+/// An sRGB colour
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    /// Parse `#rrggbb`, with or without the `#`
+    pub fn parse(s: &str) -> Option<Self> {
+        let hex = s.trim().trim_start_matches('#');
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some(Self(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    pub fn to_hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+
+    /// Mix towards `other` by `t` (0 to 1) in OKLab, so the steps look
+    /// evenly spaced and midpoints don't turn muddy
+    pub fn mix(self, other: Rgb, t: f64) -> Rgb {
+        let a = self.to_oklab();
+        let b = other.to_oklab();
+        let lerp = |x: f64, y: f64| x + (y - x) * t;
+        Rgb::from_oklab([lerp(a[0], b[0]), lerp(a[1], b[1]), lerp(a[2], b[2])])
+    }
+
+    fn to_oklab(self) -> [f64; 3] {
+        let r = to_linear(self.0);
+        let g = to_linear(self.1);
+        let b = to_linear(self.2);
+
+        let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+        let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+        let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+
+        [
+            0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+            1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+            0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+        ]
+    }
+
+    fn from_oklab([l, a, b]: [f64; 3]) -> Rgb {
+        let l_ = (l + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+        let m_ = (l - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+        let s_ = (l - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+
+        Rgb(
+            from_linear(4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_),
+            from_linear(-1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_),
+            from_linear(-0.0041960863 * l_ - 0.7034186147 * m_ + 1.7076147010 * s_),
+        )
+    }
+}
+
+fn to_linear(channel: u8) -> f64 {
+    let c = channel as f64 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn from_linear(c: f64) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round() as u8
+}
+
+/// The colours of a Ghostty theme
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Palette {
+    pub colors: BTreeMap<String, Rgb>,
+    /// The 16 (or more) indexed terminal colours
+    pub palette: BTreeMap<u8, Rgb>,
+}
+
+impl Palette {
+    /// Parse the colour settings from a Ghostty theme file, ignoring
+    /// anything else
+    pub fn parse(content: &str) -> Self {
+        let mut palette = Self::default();
+        for line in content.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let key = key.trim();
+            if key == "palette" {
+                if let Some((index, color)) = value.split_once('=') {
+                    if let (Ok(index), Some(color)) = (index.trim().parse(), Rgb::parse(color)) {
+                        palette.palette.insert(index, color);
+                    }
+                }
+            } else if COLOR_KEYS.contains(&key) {
+                if let Some(color) = Rgb::parse(value) {
+                    palette.colors.insert(key.to_string(), color);
+                }
+            }
+        }
+        palette
+    }
+
+    /// Load a Ghostty theme by name or path
+    pub fn load(theme: &str) -> Result<Self> {
+        let path = find_theme(theme).with_context(|| {
+            format!(
+                "Could not find the Ghostty theme '{}'. Fading needs the theme files on disk.",
+                theme
+            )
+        })?;
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read Ghostty theme at {:?}", path))?;
+        Ok(Self::parse(&content))
+    }
+
+    /// Blend towards `other` by `t` (0 to 1). Colours only one side sets
+    /// are left out, so Ghostty uses its defaults for them.
+    pub fn mix(&self, other: &Palette, t: f64) -> Palette {
+        let colors = self
+            .colors
+            .iter()
+            .filter_map(|(key, a)| Some((key.clone(), a.mix(*other.colors.get(key)?, t))))
+            .collect();
+        let palette = self
+            .palette
+            .iter()
+            .filter_map(|(index, a)| Some((*index, a.mix(*other.palette.get(index)?, t))))
+            .collect();
+        Palette { colors, palette }
+    }
+
+    pub fn to_ghostty(&self) -> String {
+        let mut content = String::new();
+        for (index, color) in &self.palette {
+            content.push_str(&format!("palette = {}={}\n", index, color.to_hex()));
+        }
+        for (key, color) in &self.colors {
+            content.push_str(&format!("{} = {}\n", key, color.to_hex()));
+        }
+        content
+    }
+
+    /// Write this palette as a generated theme named `name`, returning its
+    /// path for Ghostty's `theme` setting
+    pub fn write(&self, name: &str) -> Result<PathBuf> {
+        let dir = Config::generated_themes_dir()?;
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(name);
+        fs::write(&path, self.to_ghostty())
+            .with_context(|| format!("Failed to write generated theme at {:?}", path))?;
+        Ok(path)
+    }
+}
+
+/// Find a Ghostty theme file in the user and bundled theme directories
+fn find_theme(theme: &str) -> Option<PathBuf> {
+    let path = Path::new(theme);
+    if path.is_absolute() {
+        return path.exists().then(|| path.to_path_buf());
+    }
+
+    let mut dirs = Vec::new();
+    if let Some(config_dir) = dirs::config_dir() {
+        dirs.push(config_dir.join("ghostty").join("themes"));
+    }
+    if let Some(home) = dirs::home_dir() {
+        dirs.push(home.join(".config").join("ghostty").join("themes"));
+    }
+    if let Ok(resources) = std::env::var("GHOSTTY_RESOURCES_DIR") {
+        dirs.push(PathBuf::from(resources).join("themes"));
+    }
+    dirs.push(PathBuf::from(
+        "/Applications/Ghostty.app/Contents/Resources/ghostty/themes",
+    ));
+    dirs.push(PathBuf::from("/usr/share/ghostty/themes"));
+    dirs.push(PathBuf::from("/usr/local/share/ghostty/themes"));
+
+    dirs.into_iter()
+        .map(|dir| dir.join(theme))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ghostty_theme() {
+        let palette = Palette::parse(
+            "palette = 0=#191724\npalette = 15=e0def4\nbackground = #191724\n\
+             foreground = e0def4\nfont-size = 14\ncursor-color = nonsense\n",
+        );
+        assert_eq!(palette.palette[&0], Rgb(0x19, 0x17, 0x24));
+        assert_eq!(palette.palette[&15], Rgb(0xe0, 0xde, 0xf4));
+        assert_eq!(palette.colors["background"], Rgb(0x19, 0x17, 0x24));
+        assert_eq!(palette.colors.len(), 2);
+
+        assert_eq!(Palette::parse(&palette.to_ghostty()), palette);
+    }
+
+    #[test]
+    fn test_mix_in_oklab() {
+        let black = Rgb(0, 0, 0);
+        let white = Rgb(255, 255, 255);
+        assert_eq!(black.mix(white, 0.0), black);
+        assert_eq!(black.mix(white, 1.0), white);
+        // Perceptual midpoint, rather than #808080 from mixing sRGB values
+        assert_eq!(black.mix(white, 0.5), Rgb(0x63, 0x63, 0x63));
+
+        let rose = Rgb(0xeb, 0x6f, 0x92);
+        assert_eq!(rose.mix(rose, 0.3), rose);
+    }
+
+    #[test]
+    fn test_mix_palettes_keeps_shared_keys() {
+        let dark =
+            Palette::parse("background = #000000\nforeground = #ffffff\npalette = 1=#ff0000\n");
+        let light = Palette::parse("background = #ffffff\nforeground = #000000\n");
+        let mixed = dark.mix(&light, 0.5);
+        assert_eq!(mixed.colors["background"], Rgb(0x63, 0x63, 0x63));
+        assert!(mixed.palette.is_empty());
+    }
+}
@@ -0,0 +1,270 @@
+use chrono::{DateTime, FixedOffset, Local, Offset, Utc};
+use std::fmt;
+use std::io::{self, Read};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// How often a sleeping daemon compares its clocks. The monotonic clock
+/// stops during suspend, so a long sleep can't be trusted to end on time.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How far the wall clock may drift from the monotonic clock before it
+/// counts as a jump
+const JUMP_THRESHOLD: Duration = Duration::from_secs(30);
+
+/// The wall clock, monotonic clock and UTC offset at one moment
+#[derive(Debug, Clone, Copy)]
+pub struct ClockReading {
+    wall: DateTime<Utc>,
+    monotonic: Instant,
+    offset: FixedOffset,
+}
+
+/// A change to the clocks that calls for re-evaluating the theme
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockChange {
+    /// The wall clock moved this much further than the monotonic clock,
+    /// after a suspend or the clock being set
+    Jumped(chrono::Duration),
+    /// The local timezone changed, or entered or left daylight saving
+    Offset { from: FixedOffset, to: FixedOffset },
+}
+
+impl ClockReading {
+    pub fn now() -> Self {
+        let now = Local::now();
+        Self {
+            wall: now.with_timezone(&Utc),
+            monotonic: Instant::now(),
+            offset: now.offset().fix(),
+        }
+    }
+
+    /// What changed between this reading and `later`, besides time passing
+    pub fn changes(&self, later: &ClockReading) -> Option<ClockChange> {
+        let wall = later.wall - self.wall;
+        let monotonic = chrono::Duration::from_std(later.monotonic - self.monotonic).ok()?;
+        let drift = wall - monotonic;
+        if drift.abs() > chrono::Duration::from_std(JUMP_THRESHOLD).ok()? {
+            return Some(ClockChange::Jumped(drift));
+        }
+        if later.offset != self.offset {
+            return Some(ClockChange::Offset {
+                from: self.offset,
+                to: later.offset,
+            });
+        }
+        None
+    }
+}
+
+impl fmt::Display for ClockChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClockChange::Jumped(drift) => {
+                let minutes = drift.num_minutes().abs();
+                let seconds = drift.num_seconds().abs() % 60;
+                let direction = if *drift > chrono::Duration::zero() {
+                    "ahead"
+                } else {
+                    "back"
+                };
+                write!(
+                    f,
+                    "Clock jumped {}m {}s {} (suspend or clock change)",
+                    minutes, seconds, direction
+                )
+            }
+            ClockChange::Offset { from, to } => {
+                write!(f, "UTC offset changed from {} to {}", from, to)
+            }
+        }
+    }
+}
+
+/// A logind sleep notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SleepEvent {
+    Suspending,
+    Resumed,
+}
+
+/// Follows logind's `PrepareForSleep` D-Bus signal through `gdbus monitor`,
+/// so the daemon hears about a resume as soon as it happens
+pub struct SleepMonitor {
+    child: Child,
+    stdout: Option<ChildStdout>,
+    buf: Vec<u8>,
+}
+
+impl SleepMonitor {
+    /// Start watching, if this is Linux and `gdbus` is installed. The
+    /// monitor stops by itself when there's no system bus or logind.
+    pub fn start() -> Option<Self> {
+        if !cfg!(target_os = "linux") {
+            return None;
+        }
+        let mut child = Command::new("gdbus")
+            .args([
+                "monitor",
+                "--system",
+                "--dest",
+                "org.freedesktop.login1",
+                "--object-path",
+                "/org/freedesktop/login1",
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let stdout = child.stdout.take()?;
+        unsafe {
+            let flags = libc::fcntl(stdout.as_raw_fd(), libc::F_GETFL);
+            libc::fcntl(stdout.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK);
+        }
+        Some(Self {
+            child,
+            stdout: Some(stdout),
+            buf: Vec::new(),
+        })
+    }
+
+    /// The descriptor to poll for signals, until the monitor exits
+    pub fn fd(&self) -> Option<RawFd> {
+        self.stdout.as_ref().map(AsRawFd::as_raw_fd)
+    }
+
+    /// The sleep notifications received since the last call
+    pub fn events(&mut self) -> Vec<SleepEvent> {
+        let Some(stdout) = &mut self.stdout else {
+            return Vec::new();
+        };
+
+        let mut chunk = [0u8; 1024];
+        loop {
+            match stdout.read(&mut chunk) {
+                Ok(0) => {
+                    // gdbus exited; stop polling its closed pipe
+                    self.stdout = None;
+                    let _ = self.child.wait();
+                    break;
+                }
+                Ok(len) => self.buf.extend_from_slice(&chunk[..len]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+
+        let mut events = Vec::new();
+        while let Some(end) = self.buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=end).collect();
+            events.extend(parse_signal(&String::from_utf8_lossy(&line)));
+        }
+        events
+    }
+}
+
+impl Drop for SleepMonitor {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Read a line of `gdbus monitor` output such as
+/// `/org/freedesktop/login1: org.freedesktop.login1.Manager.PrepareForSleep (false,)`
+fn parse_signal(line: &str) -> Option<SleepEvent> {
+    let (_, args) = line.split_once("org.freedesktop.login1.Manager.PrepareForSleep (")?;
+    match args.split([',', ')']).next()?.trim() {
+        "true" => Some(SleepEvent::Suspending),
+        "false" => Some(SleepEvent::Resumed),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn reading(offset_hours: i32) -> ClockReading {
+        ClockReading {
+            wall: Utc.with_ymd_and_hms(2024, 3, 20, 22, 0, 0).unwrap(),
+            monotonic: Instant::now(),
+            offset: FixedOffset::east_opt(offset_hours * 3600).unwrap(),
+        }
+    }
+
+    fn after(before: &ClockReading, wall: i64, monotonic: u64) -> ClockReading {
+        ClockReading {
+            wall: before.wall + chrono::Duration::seconds(wall),
+            monotonic: before.monotonic + Duration::from_secs(monotonic),
+            offset: before.offset,
+        }
+    }
+
+    #[test]
+    fn test_clock_jumps() {
+        let before = reading(0);
+        assert_eq!(before.changes(&after(&before, 60, 60)), None);
+        assert_eq!(before.changes(&after(&before, 70, 60)), None);
+
+        // Overnight suspend: the monotonic clock only saw the minute awake
+        let change = before.changes(&after(&before, 9 * 3600, 60)).unwrap();
+        assert_eq!(
+            change,
+            ClockChange::Jumped(chrono::Duration::seconds(9 * 3600 - 60))
+        );
+        assert_eq!(
+            change.to_string(),
+            "Clock jumped 539m 0s ahead (suspend or clock change)"
+        );
+
+        let change = before.changes(&after(&before, -600, 60)).unwrap();
+        assert!(change.to_string().contains("11m 0s back"));
+    }
+
+    #[test]
+    fn test_offset_change() {
+        let before = reading(0);
+        let later = ClockReading {
+            offset: FixedOffset::east_opt(3600).unwrap(),
+            ..after(&before, 60, 60)
+        };
+        assert_eq!(
+            before.changes(&later),
+            Some(ClockChange::Offset {
+                from: before.offset,
+                to: later.offset,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_prepare_for_sleep() {
+        assert_eq!(
+            parse_signal(
+                "/org/freedesktop/login1: org.freedesktop.login1.Manager.PrepareForSleep (true,)"
+            ),
+            Some(SleepEvent::Suspending)
+        );
+        assert_eq!(
+            parse_signal(
+                "/org/freedesktop/login1: org.freedesktop.login1.Manager.PrepareForSleep (false,)\n"
+            ),
+            Some(SleepEvent::Resumed)
+        );
+        assert_eq!(
+            parse_signal("The name org.freedesktop.login1 is owned by :1.4"),
+            None
+        );
+        assert_eq!(
+            parse_signal(
+                "/org/freedesktop/login1: org.freedesktop.login1.Manager.SessionNew ('3', objectpath '/org/freedesktop/login1/session/_33')"
+            ),
+            None
+        );
+    }
+}
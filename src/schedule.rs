@@ -0,0 +1,1406 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, Exception, FixedTimes, Phase, PhaseStart, ScheduleMode, SunDirection};
+use crate::solar::{self, Crossing};
+use crate::sun_times::{DayKind, SunSource, SunTimes, ThemeMode};
+
+/// A point in the sun's daily path that can trigger a theme switch
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawSolarEvent", into = "RawSolarEvent")]
+pub enum SolarEvent {
+    Sunrise,
+    Sunset,
+    CivilDawn,
+    CivilDusk,
+    NauticalDawn,
+    NauticalDusk,
+    AstronomicalDawn,
+    AstronomicalDusk,
+    /// Evening golden hour begins (sun descending through +6°)
+    GoldenHourStart,
+    /// Morning golden hour ends (sun rising through +6°)
+    GoldenHourEnd,
+    /// Sun crosses this elevation in degrees. Rising when used for
+    /// `light_at`, setting when used for `dark_at`, and as given by
+    /// `direction` for phases.
+    Elevation(f64),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawSolarEvent {
+    Name(String),
+    Angle(f64),
+}
+
+impl TryFrom<RawSolarEvent> for SolarEvent {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawSolarEvent) -> Result<Self> {
+        match raw {
+            RawSolarEvent::Name(name) => name.parse(),
+            RawSolarEvent::Angle(angle) => SolarEvent::elevation_of(angle)
+                .with_context(|| format!("Invalid elevation: {}°. Use -90 to 90.", angle)),
+        }
+    }
+}
+
+impl From<SolarEvent> for RawSolarEvent {
+    fn from(event: SolarEvent) -> Self {
+        match event {
+            SolarEvent::Elevation(angle) => RawSolarEvent::Angle(angle),
+            other => RawSolarEvent::Name(other.to_string()),
+        }
+    }
+}
+
+impl SolarEvent {
+    /// The sun crossing `angle`, if the sun can be there
+    fn elevation_of(angle: f64) -> Option<Self> {
+        (-90.0..=90.0)
+            .contains(&angle)
+            .then_some(SolarEvent::Elevation(angle))
+    }
+
+    /// Elevation of the sun at this event, and whether the sun is rising.
+    /// `rising` is only used for plain elevation angles.
+    fn elevation(&self, rising: bool) -> (f64, bool) {
+        match self {
+            SolarEvent::Sunrise => (solar::SUNRISE_ELEVATION, true),
+            SolarEvent::Sunset => (solar::SUNRISE_ELEVATION, false),
+            SolarEvent::CivilDawn => (-6.0, true),
+            SolarEvent::CivilDusk => (-6.0, false),
+            SolarEvent::NauticalDawn => (-12.0, true),
+            SolarEvent::NauticalDusk => (-12.0, false),
+            SolarEvent::AstronomicalDawn => (-18.0, true),
+            SolarEvent::AstronomicalDusk => (-18.0, false),
+            SolarEvent::GoldenHourStart => (6.0, false),
+            SolarEvent::GoldenHourEnd => (6.0, true),
+            SolarEvent::Elevation(angle) => (*angle, rising),
+        }
+    }
+
+    /// Find this event on the day described by `sun_times`. Sunrise and
+    /// sunset come from the provider; everything else is computed locally.
+    fn crossing_on(
+        &self,
+        sun_times: &SunTimes,
+        latitude: f64,
+        longitude: f64,
+        rising: bool,
+    ) -> Crossing {
+        let from_provider = match self {
+            SolarEvent::Sunrise => Some(sun_times.sunrise),
+            SolarEvent::Sunset => Some(sun_times.sunset),
+            _ => None,
+        };
+
+        if let Some(time) = from_provider {
+            return match (time, sun_times.kind) {
+                (Some(time), _) => Crossing::At(time),
+                (None, DayKind::PolarNight) => Crossing::AlwaysBelow,
+                (None, _) => Crossing::AlwaysAbove,
+            };
+        }
+
+        let (elevation, rising) = self.elevation(rising);
+        solar::crossing(sun_times.date, latitude, longitude, elevation, rising)
+    }
+}
+
+impl std::fmt::Display for SolarEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SolarEvent::Sunrise => "sunrise",
+            SolarEvent::Sunset => "sunset",
+            SolarEvent::CivilDawn => "civil_dawn",
+            SolarEvent::CivilDusk => "civil_dusk",
+            SolarEvent::NauticalDawn => "nautical_dawn",
+            SolarEvent::NauticalDusk => "nautical_dusk",
+            SolarEvent::AstronomicalDawn => "astronomical_dawn",
+            SolarEvent::AstronomicalDusk => "astronomical_dusk",
+            SolarEvent::GoldenHourStart => "golden_hour_start",
+            SolarEvent::GoldenHourEnd => "golden_hour_end",
+            SolarEvent::Elevation(angle) => return write!(f, "{}°", angle),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for SolarEvent {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "sunrise" => Ok(SolarEvent::Sunrise),
+            "sunset" => Ok(SolarEvent::Sunset),
+            "civil_dawn" => Ok(SolarEvent::CivilDawn),
+            "civil_dusk" => Ok(SolarEvent::CivilDusk),
+            "nautical_dawn" => Ok(SolarEvent::NauticalDawn),
+            "nautical_dusk" => Ok(SolarEvent::NauticalDusk),
+            "astronomical_dawn" => Ok(SolarEvent::AstronomicalDawn),
+            "astronomical_dusk" => Ok(SolarEvent::AstronomicalDusk),
+            "golden_hour_start" => Ok(SolarEvent::GoldenHourStart),
+            "golden_hour_end" => Ok(SolarEvent::GoldenHourEnd),
+            other => match other
+                .trim_end_matches('°')
+                .parse::<f64>()
+                .ok()
+                .and_then(SolarEvent::elevation_of)
+            {
+                Some(event) => Ok(event),
+                None => anyhow::bail!(
+                    "Invalid solar event: {}. Use sunrise, sunset, civil_dawn, civil_dusk, \
+                     nautical_dawn, nautical_dusk, astronomical_dawn, astronomical_dusk, \
+                     golden_hour_start, golden_hour_end or an elevation in degrees.",
+                    s
+                ),
+            },
+        }
+    }
+}
+
+/// The day's switching times, resolved from sun times and the configured
+/// `[schedule]` thresholds
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    /// The schedule mode in effect for this day, after rules
+    pub mode: ScheduleMode,
+    /// `None` unless the day follows the sun
+    pub sun_times: Option<SunTimes>,
+    /// Where the day's times came from, when it follows the sun or had to
+    /// fall back to fixed times
+    pub source: Option<SunSource>,
+    /// Local midnight at the start of this schedule's day
+    pub day_start: DateTime<Utc>,
+    /// Local midnight at the start of the next day
+    pub day_end: DateTime<Utc>,
+    /// `[[exceptions]]` overlapping this day, which override everything else
+    pub exceptions: Vec<ExceptionWindow>,
+    /// Threshold event times before offsets and clamps
+    pub raw_light_at: Option<DateTime<Utc>>,
+    pub raw_dark_at: Option<DateTime<Utc>>,
+    /// Effective switching times. `None` when the mode is held all day.
+    pub light_at: Option<DateTime<Utc>>,
+    pub dark_at: Option<DateTime<Utc>>,
+    /// Set when a threshold is never crossed today (polar day or night),
+    /// to the mode the sun's position implies for the whole day
+    pub steady: Option<ThemeMode>,
+    /// Today's phases in order. When any are set they decide the mode
+    /// instead of `light_at` and `dark_at`.
+    pub phases: Vec<PhaseTime>,
+    /// The phase in effect until today's first one begins: the last phase
+    /// of the day before, when that day had any
+    pub carried_over: Option<PhaseTime>,
+}
+
+/// A switch to `mode`, and to `phase` when phases are configured, at the
+/// instant `at`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transition {
+    pub at: DateTime<Utc>,
+    pub mode: ThemeMode,
+    pub phase: Option<String>,
+}
+
+impl Transition {
+    pub fn at_local(&self) -> DateTime<Local> {
+        self.at.with_timezone(&Local)
+    }
+
+    /// The phase and mode, or just the mode without phases
+    pub fn label(&self) -> String {
+        match &self.phase {
+            Some(phase) => format!("{} ({})", phase, self.mode),
+            None => self.mode.to_string(),
+        }
+    }
+}
+
+/// A `[[phases]]` entry resolved to the instant it begins on a given day
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhaseTime {
+    pub name: String,
+    pub mode: ThemeMode,
+    pub at: DateTime<Utc>,
+}
+
+impl PhaseTime {
+    pub fn at_local(&self) -> DateTime<Local> {
+        self.at.with_timezone(&Local)
+    }
+}
+
+/// An `[[exceptions]]` entry resolved to UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExceptionWindow {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub mode: ThemeMode,
+}
+
+impl ExceptionWindow {
+    pub fn contains(&self, at: DateTime<Utc>) -> bool {
+        at >= self.start && at < self.end
+    }
+
+    pub fn end_local(&self) -> DateTime<Local> {
+        self.end.with_timezone(&Local)
+    }
+}
+
+/// How many days ahead to look for the next switch
+pub const SEARCH_DAYS: i64 = 7;
+
+/// Resolve a local wall-clock time on `date` to UTC
+fn local_time_on<Tz: TimeZone>(tz: &Tz, date: NaiveDate, time: NaiveTime) -> Option<DateTime<Utc>> {
+    tz.from_local_datetime(&date.and_time(time))
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Start of `date` in the given timezone, skipping forward past a DST gap
+/// at midnight
+fn day_start_in<Tz: TimeZone>(tz: &Tz, date: NaiveDate) -> DateTime<Utc> {
+    (0..3)
+        .find_map(|hour| local_time_on(tz, date, NaiveTime::from_hms_opt(hour, 0, 0)?))
+        .unwrap_or_else(|| date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+}
+
+/// The next `count` switches after `now`, using the real sun times for
+/// each day ahead
+pub fn next_transitions(
+    config: &Config,
+    now: DateTime<Utc>,
+    count: usize,
+) -> Result<Vec<Transition>> {
+    transitions_after(&Local, now, count, |date| Schedule::for_date(config, date))
+}
+
+fn transitions_after<Tz, F>(
+    tz: &Tz,
+    now: DateTime<Utc>,
+    count: usize,
+    mut day: F,
+) -> Result<Vec<Transition>>
+where
+    Tz: TimeZone,
+    F: FnMut(NaiveDate) -> Result<Schedule>,
+{
+    let today = now.with_timezone(tz).date_naive();
+    let mut schedule = day(today)?;
+    let mut state = schedule.state_at(now);
+    let mut transitions = Vec::new();
+
+    for offset in 0..SEARCH_DAYS + count as i64 {
+        if offset > 0 {
+            schedule = day(today + Duration::days(offset))?;
+        }
+
+        // The mode can only change at the start of the day, at one of the
+        // day's switching times or phases, or where an exception starts or
+        // ends
+        let exception_bounds = schedule
+            .exceptions
+            .iter()
+            .flat_map(|e| [e.start, e.end])
+            .filter(|at| *at > schedule.day_start && *at < schedule.day_end);
+        let mut candidates: Vec<DateTime<Utc>> = [
+            Some(schedule.day_start),
+            schedule.light_at,
+            schedule.dark_at,
+        ]
+        .into_iter()
+        .flatten()
+        .chain(schedule.phases.iter().map(|p| p.at))
+        .chain(exception_bounds)
+        .filter(|at| *at > now)
+        .collect();
+        candidates.sort();
+
+        for at in candidates {
+            let next = schedule.state_at(at);
+            if next != state {
+                transitions.push(Transition {
+                    at,
+                    mode: next.0,
+                    phase: next.1.clone(),
+                });
+                state = next;
+                if transitions.len() == count {
+                    return Ok(transitions);
+                }
+            }
+        }
+    }
+
+    Ok(transitions)
+}
+
+/// Find when each phase begins on the day described by `sun_times`,
+/// skipping phases whose solar event doesn't happen that day
+fn resolve_phases<Tz: TimeZone>(
+    phases: &[Phase],
+    sun_times: &SunTimes,
+    lat: f64,
+    lon: f64,
+    tz: &Tz,
+) -> Vec<PhaseTime> {
+    let mut resolved: Vec<PhaseTime> = phases
+        .iter()
+        .filter_map(|phase| {
+            let at = match phase.at {
+                PhaseStart::Time(time) => local_time_on(tz, sun_times.date, time)?,
+                PhaseStart::Event(event) => {
+                    // Named events know their own direction; an angle
+                    // without one is refused by `Config::validate`
+                    let rising = match (event, phase.direction) {
+                        (_, Some(direction)) => direction == SunDirection::Rising,
+                        (SolarEvent::Elevation(_), None) => return None,
+                        (_, None) => true,
+                    };
+                    match event.crossing_on(sun_times, lat, lon, rising) {
+                        Crossing::At(at) => at,
+                        _ => return None,
+                    }
+                }
+            };
+            Some(PhaseTime {
+                name: phase.name.clone(),
+                mode: phase.mode,
+                at,
+            })
+        })
+        .collect();
+    resolved.sort_by_key(|p| p.at);
+    resolved
+}
+
+impl Schedule {
+    pub fn resolve(config: &Config, sun_times: SunTimes) -> Result<Self> {
+        Self::resolve_in(config, sun_times, &Local)
+    }
+
+    /// Resolve with clock times interpreted in the given timezone
+    fn resolve_in<Tz: TimeZone>(config: &Config, sun_times: SunTimes, tz: &Tz) -> Result<Self> {
+        let location = config.require_location()?;
+        let (lat, lon) = (location.latitude, location.longitude);
+        let mut schedule = Self::thresholds_in(config, sun_times, lat, lon, tz)?;
+
+        if let Some(sun_times) = &schedule.sun_times {
+            schedule.phases = resolve_phases(&config.phases, sun_times, lat, lon, tz);
+        }
+        Ok(schedule)
+    }
+
+    /// Resolve the `light_at` and `dark_at` thresholds with offsets and
+    /// clamps applied
+    fn thresholds_in<Tz: TimeZone>(
+        config: &Config,
+        sun_times: SunTimes,
+        lat: f64,
+        lon: f64,
+        tz: &Tz,
+    ) -> Result<Self> {
+        let schedule = &config.schedule;
+        let light = schedule.light_at.crossing_on(&sun_times, lat, lon, true);
+        let dark = schedule.dark_at.crossing_on(&sun_times, lat, lon, false);
+
+        let (raw_light_at, raw_dark_at) = match (light, dark) {
+            (Crossing::At(light), Crossing::At(dark)) => (light, dark),
+            _ => {
+                let missing = if matches!(light, Crossing::At(_)) {
+                    dark
+                } else {
+                    light
+                };
+                let steady = if missing == Crossing::AlwaysAbove {
+                    ThemeMode::Light
+                } else {
+                    ThemeMode::Dark
+                };
+                return Self::steady(schedule.polar_fallback, sun_times, steady, tz);
+            }
+        };
+
+        let mut light_at = raw_light_at + Duration::minutes(schedule.light_offset_minutes);
+        let mut dark_at = raw_dark_at + Duration::minutes(schedule.dark_offset_minutes);
+
+        if let Some(earliest) = schedule
+            .earliest_light
+            .and_then(|t| local_time_on(tz, sun_times.date, t))
+        {
+            light_at = light_at.max(earliest);
+        }
+        if let Some(latest) = schedule
+            .latest_dark
+            .and_then(|t| local_time_on(tz, sun_times.date, t))
+        {
+            dark_at = dark_at.min(latest);
+        }
+
+        Ok(Self {
+            mode: ScheduleMode::Sun,
+            day_start: day_start_in(tz, sun_times.date),
+            day_end: day_start_in(tz, sun_times.date + Duration::days(1)),
+            exceptions: Vec::new(),
+            phases: Vec::new(),
+            carried_over: None,
+            source: None,
+            sun_times: Some(sun_times),
+            raw_light_at: Some(raw_light_at),
+            raw_dark_at: Some(raw_dark_at),
+            light_at: Some(light_at),
+            dark_at: Some(dark_at),
+            steady: None,
+        })
+    }
+
+    /// A day without threshold crossings: hold `steady` all day, or switch
+    /// at the fallback clock times if configured
+    fn steady<Tz: TimeZone>(
+        fallback: Option<FixedTimes>,
+        sun_times: SunTimes,
+        steady: ThemeMode,
+        tz: &Tz,
+    ) -> Result<Self> {
+        let (light_at, dark_at) = match fallback {
+            Some(times) => (
+                local_time_on(tz, sun_times.date, times.light),
+                local_time_on(tz, sun_times.date, times.dark),
+            ),
+            None => (None, None),
+        };
+
+        Ok(Self {
+            mode: ScheduleMode::Sun,
+            day_start: day_start_in(tz, sun_times.date),
+            day_end: day_start_in(tz, sun_times.date + Duration::days(1)),
+            exceptions: Vec::new(),
+            phases: Vec::new(),
+            carried_over: None,
+            source: None,
+            sun_times: Some(sun_times),
+            raw_light_at: None,
+            raw_dark_at: None,
+            light_at,
+            dark_at,
+            steady: Some(steady),
+        })
+    }
+
+    /// A day switching at the same clock times regardless of the sun
+    fn fixed_in<Tz: TimeZone>(times: FixedTimes, date: NaiveDate, tz: &Tz) -> Self {
+        let light_at = local_time_on(tz, date, times.light);
+        let dark_at = local_time_on(tz, date, times.dark);
+
+        Self {
+            mode: ScheduleMode::Fixed,
+            sun_times: None,
+            day_start: day_start_in(tz, date),
+            day_end: day_start_in(tz, date + Duration::days(1)),
+            exceptions: Vec::new(),
+            phases: Vec::new(),
+            carried_over: None,
+            source: None,
+            raw_light_at: light_at,
+            raw_dark_at: dark_at,
+            light_at,
+            dark_at,
+            steady: None,
+        }
+    }
+
+    /// A day held in one mode by a `light` or `dark` schedule mode
+    fn held_in<Tz: TimeZone>(
+        mode: ScheduleMode,
+        held: ThemeMode,
+        date: NaiveDate,
+        tz: &Tz,
+    ) -> Self {
+        Self {
+            mode,
+            sun_times: None,
+            day_start: day_start_in(tz, date),
+            day_end: day_start_in(tz, date + Duration::days(1)),
+            exceptions: Vec::new(),
+            phases: Vec::new(),
+            carried_over: None,
+            source: None,
+            raw_light_at: None,
+            raw_dark_at: None,
+            light_at: None,
+            dark_at: None,
+            steady: Some(held),
+        }
+    }
+
+    /// Carry the last phase of `previous`, the day before, over into the
+    /// start of this day
+    fn after(mut self, previous: &Schedule) -> Self {
+        if !self.phases.is_empty() {
+            self.carried_over = previous.phases.last().cloned();
+        }
+        self
+    }
+
+    /// Overlay the exceptions that overlap this day
+    fn with_exceptions<Tz: TimeZone>(mut self, exceptions: &[Exception], tz: &Tz) -> Self {
+        let to_utc = |at: &NaiveDateTime| {
+            tz.from_local_datetime(at)
+                .earliest()
+                .map(|t| t.with_timezone(&Utc))
+        };
+        self.exceptions = exceptions
+            .iter()
+            .filter_map(|e| {
+                Some(ExceptionWindow {
+                    start: to_utc(&e.start)?,
+                    end: to_utc(&e.end)?,
+                    mode: e.mode,
+                })
+            })
+            .filter(|w| w.start < self.day_end && w.end > self.day_start)
+            .collect();
+        self
+    }
+
+    /// The schedule for `date` under the mode its rules select, using the
+    /// configured sun times provider for sun-based days. Exceptions are
+    /// applied on top.
+    pub fn for_date(config: &Config, date: NaiveDate) -> Result<Self> {
+        let mut schedule = Self::for_date_without_exceptions(config, date)?;
+        if !schedule.phases.is_empty() {
+            let previous = Self::for_date_without_exceptions(config, date - Duration::days(1))?;
+            schedule = schedule.after(&previous);
+        }
+        Ok(schedule.with_exceptions(&config.exceptions, &Local))
+    }
+
+    fn for_date_without_exceptions(config: &Config, date: NaiveDate) -> Result<Self> {
+        let mode = config.schedule.mode_on(date);
+        match mode {
+            ScheduleMode::Fixed => Ok(Self::fixed_in(
+                config.schedule.fixed_times_on(date)?,
+                date,
+                &Local,
+            )),
+            ScheduleMode::Light => Ok(Self::held_in(mode, ThemeMode::Light, date, &Local)),
+            ScheduleMode::Dark => Ok(Self::held_in(mode, ThemeMode::Dark, date, &Local)),
+            ScheduleMode::Sun => {
+                let (sun_times, source) = SunTimes::get_with_fallback(config, date)?;
+                Ok(Self {
+                    source: Some(source),
+                    ..Self::resolve(config, sun_times)?
+                })
+            }
+        }
+    }
+
+    /// The last resort when `for_date` fails, so the daemon can still pick
+    /// a theme: `[schedule.fixed]` if usable, otherwise 07:00 to 19:00
+    pub fn fallback(config: &Config, date: NaiveDate) -> Self {
+        let times = config.schedule.fixed_times().unwrap_or(FixedTimes {
+            light: NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+            dark: NaiveTime::from_hms_opt(19, 0, 0).unwrap(),
+        });
+        Self {
+            source: Some(SunSource::Fixed),
+            ..Self::fixed_in(times, date, &Local)
+        }
+        .with_exceptions(&config.exceptions, &Local)
+    }
+
+    /// Today's schedule
+    pub fn today(config: &Config) -> Result<Self> {
+        Self::for_date(config, Local::now().date_naive())
+    }
+
+    pub fn light_at_local(&self) -> Option<DateTime<Local>> {
+        self.light_at.map(|t| t.with_timezone(&Local))
+    }
+
+    pub fn dark_at_local(&self) -> Option<DateTime<Local>> {
+        self.dark_at.map(|t| t.with_timezone(&Local))
+    }
+
+    pub fn raw_light_at_local(&self) -> Option<DateTime<Local>> {
+        self.raw_light_at.map(|t| t.with_timezone(&Local))
+    }
+
+    pub fn raw_dark_at_local(&self) -> Option<DateTime<Local>> {
+        self.raw_dark_at.map(|t| t.with_timezone(&Local))
+    }
+
+    /// Whether the switching times differ from plain sunrise/sunset
+    pub fn is_custom(&self) -> bool {
+        match &self.sun_times {
+            Some(sun_times) => {
+                self.light_at != sun_times.sunrise || self.dark_at != sun_times.sunset
+            }
+            None => true,
+        }
+    }
+
+    /// Whether this day follows fixed clock times rather than the sun
+    pub fn is_fixed(&self) -> bool {
+        self.mode == ScheduleMode::Fixed
+    }
+
+    /// Whether offsets or clamps moved the switching times away from the
+    /// threshold events
+    pub fn is_adjusted(&self) -> bool {
+        self.light_at != self.raw_light_at || self.dark_at != self.raw_dark_at
+    }
+
+    /// Whether the polar fallback clock times are in use today
+    pub fn uses_fallback(&self) -> bool {
+        self.steady.is_some() && self.light_at.is_some()
+    }
+
+    /// The exception in force at `now`, if any
+    pub fn exception_at(&self, now: DateTime<Utc>) -> Option<&ExceptionWindow> {
+        self.exceptions.iter().find(|e| e.contains(now))
+    }
+
+    /// The phase in effect at `now`. Before the day's first phase begins,
+    /// the last one of the day before carries over.
+    pub fn phase_at(&self, now: DateTime<Utc>) -> Option<&PhaseTime> {
+        if self.exception_at(now).is_some() {
+            return None;
+        }
+        self.phases
+            .iter()
+            .rev()
+            .find(|p| p.at <= now)
+            .or(self.carried_over.as_ref())
+    }
+
+    /// The mode and phase name at `now`
+    fn state_at(&self, now: DateTime<Utc>) -> (ThemeMode, Option<String>) {
+        (
+            self.mode_at(now),
+            self.phase_at(now).map(|p| p.name.clone()),
+        )
+    }
+
+    pub fn mode_at(&self, now: DateTime<Utc>) -> ThemeMode {
+        if let Some(exception) = self.exception_at(now) {
+            return exception.mode;
+        }
+        if let Some(phase) = self.phase_at(now) {
+            return phase.mode;
+        }
+
+        match (self.light_at, self.dark_at) {
+            (Some(light_at), Some(dark_at)) if now >= light_at && now < dark_at => ThemeMode::Light,
+            (Some(_), Some(_)) => ThemeMode::Dark,
+            _ => self.steady.unwrap_or(ThemeMode::Dark),
+        }
+    }
+
+    /// The mode at `now` and the sun threshold crossing today that brought
+    /// it, when the mode comes from `light_at` or `dark_at` rather than
+    /// phases, exceptions or clock times
+    pub fn crossed_at(&self, now: DateTime<Utc>) -> Option<(ThemeMode, DateTime<Utc>)> {
+        if self.sun_times.is_none()
+            || self.uses_fallback()
+            || !self.phases.is_empty()
+            || self.exception_at(now).is_some()
+        {
+            return None;
+        }
+        let mode = self.mode_at(now);
+        let at = match mode {
+            ThemeMode::Light => self.light_at,
+            ThemeMode::Dark => self.dark_at,
+        };
+        at.filter(|at| *at <= now).map(|at| (mode, at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{parse_local_datetime, Location, ScheduleConfig};
+
+    fn london_config(light_at: SolarEvent, dark_at: SolarEvent) -> Config {
+        Config {
+            location: Some(Location {
+                latitude: 51.5074,
+                longitude: -0.1278,
+            }),
+            schedule: ScheduleConfig {
+                light_at,
+                dark_at,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn london_equinox() -> SunTimes {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        SunTimes::compute(51.5074, -0.1278, date)
+    }
+
+    fn tromso(month: u32) -> (Config, SunTimes) {
+        let config = Config {
+            location: Some(Location {
+                latitude: 69.6492,
+                longitude: 18.9553,
+            }),
+            ..Default::default()
+        };
+        let date = NaiveDate::from_ymd_opt(2024, month, 21).unwrap();
+        let sun_times = SunTimes::compute(69.6492, 18.9553, date);
+        (config, sun_times)
+    }
+
+    #[test]
+    fn test_solar_event_from_str() {
+        assert_eq!(
+            "sunrise".parse::<SolarEvent>().unwrap(),
+            SolarEvent::Sunrise
+        );
+        assert_eq!(
+            "Civil_Dusk".parse::<SolarEvent>().unwrap(),
+            SolarEvent::CivilDusk
+        );
+        assert_eq!(
+            "golden_hour_start".parse::<SolarEvent>().unwrap(),
+            SolarEvent::GoldenHourStart
+        );
+        assert_eq!(
+            "-4.5".parse::<SolarEvent>().unwrap(),
+            SolarEvent::Elevation(-4.5)
+        );
+        assert!("noon".parse::<SolarEvent>().is_err());
+        assert!("120".parse::<SolarEvent>().is_err());
+    }
+
+    #[test]
+    fn test_schedule_config_from_toml() {
+        let config: ScheduleConfig =
+            toml::from_str("light_at = \"civil_dawn\"\ndark_at = -3.5\n").unwrap();
+        assert_eq!(config.light_at, SolarEvent::CivilDawn);
+        assert_eq!(config.dark_at, SolarEvent::Elevation(-3.5));
+
+        let config: ScheduleConfig = toml::from_str("").unwrap();
+        assert_eq!(config.light_at, SolarEvent::Sunrise);
+        assert_eq!(config.dark_at, SolarEvent::Sunset);
+
+        // Bare numbers get the same range check as strings
+        assert!(toml::from_str::<ScheduleConfig>("dark_at = 120.0\n").is_err());
+        assert!(toml::from_str::<ScheduleConfig>("light_at = nan\n").is_err());
+    }
+
+    #[test]
+    fn test_default_schedule_uses_sunrise_and_sunset() {
+        let config = london_config(SolarEvent::Sunrise, SolarEvent::Sunset);
+        let sun_times = london_equinox();
+        let schedule = Schedule::resolve(&config, sun_times.clone()).unwrap();
+        assert_eq!(schedule.light_at, sun_times.sunrise);
+        assert_eq!(schedule.dark_at, sun_times.sunset);
+        assert!(!schedule.is_custom());
+    }
+
+    #[test]
+    fn test_twilight_thresholds() {
+        let sun_times = london_equinox();
+
+        let civil = Schedule::resolve(
+            &london_config(SolarEvent::CivilDawn, SolarEvent::CivilDusk),
+            sun_times.clone(),
+        )
+        .unwrap();
+        assert!(civil.light_at < sun_times.sunrise);
+        assert!(civil.dark_at > sun_times.sunset);
+
+        let nautical = Schedule::resolve(
+            &london_config(SolarEvent::NauticalDawn, SolarEvent::NauticalDusk),
+            sun_times.clone(),
+        )
+        .unwrap();
+        assert!(nautical.light_at < civil.light_at);
+        assert!(nautical.dark_at > civil.dark_at);
+
+        let golden = Schedule::resolve(
+            &london_config(SolarEvent::GoldenHourEnd, SolarEvent::GoldenHourStart),
+            sun_times.clone(),
+        )
+        .unwrap();
+        assert!(golden.light_at > sun_times.sunrise);
+        assert!(golden.dark_at < sun_times.sunset);
+        assert!(golden.is_custom());
+    }
+
+    #[test]
+    fn test_elevation_threshold_direction() {
+        let sun_times = london_equinox();
+        let schedule = Schedule::resolve(
+            &london_config(SolarEvent::Elevation(10.0), SolarEvent::Elevation(10.0)),
+            sun_times.clone(),
+        )
+        .unwrap();
+        assert!(schedule.light_at > sun_times.sunrise);
+        assert!(schedule.dark_at < sun_times.sunset);
+        assert!(schedule.light_at < schedule.dark_at);
+    }
+
+    #[test]
+    fn test_offsets() {
+        let mut config = london_config(SolarEvent::Sunrise, SolarEvent::Sunset);
+        config.schedule.light_offset_minutes = 30;
+        config.schedule.dark_offset_minutes = -45;
+        let sun_times = london_equinox();
+        let schedule = Schedule::resolve_in(&config, sun_times.clone(), &Utc).unwrap();
+
+        assert_eq!(schedule.raw_light_at, sun_times.sunrise);
+        assert_eq!(schedule.raw_dark_at, sun_times.sunset);
+        assert_eq!(
+            schedule.light_at,
+            sun_times.sunrise.map(|t| t + Duration::minutes(30))
+        );
+        assert_eq!(
+            schedule.dark_at,
+            sun_times.sunset.map(|t| t - Duration::minutes(45))
+        );
+        assert!(schedule.is_adjusted());
+    }
+
+    #[test]
+    fn test_crossed_at() {
+        let config = london_config(SolarEvent::Sunrise, SolarEvent::Sunset);
+        let sun_times = london_equinox();
+        let schedule = Schedule::resolve_in(&config, sun_times.clone(), &Utc).unwrap();
+        let at = |h| Utc.with_ymd_and_hms(2024, 3, 20, h, 0, 0).unwrap();
+
+        // The provider's times are kept
+        assert_eq!(
+            schedule.crossed_at(at(12)),
+            Some((ThemeMode::Light, sun_times.sunrise.unwrap()))
+        );
+        assert_eq!(
+            schedule.crossed_at(at(21)),
+            Some((ThemeMode::Dark, sun_times.sunset.unwrap()))
+        );
+        // Last night's crossing belongs to yesterday
+        assert_eq!(schedule.crossed_at(at(3)), None);
+    }
+
+    #[test]
+    fn test_clamps() {
+        let mut config = london_config(SolarEvent::CivilDawn, SolarEvent::CivilDusk);
+        config.schedule.earliest_light = Some(NaiveTime::from_hms_opt(7, 0, 0).unwrap());
+        config.schedule.latest_dark = Some(NaiveTime::from_hms_opt(17, 30, 0).unwrap());
+        let schedule = Schedule::resolve_in(&config, london_equinox(), &Utc).unwrap();
+
+        assert_eq!(
+            schedule.light_at,
+            Utc.with_ymd_and_hms(2024, 3, 20, 7, 0, 0).single()
+        );
+        assert_eq!(
+            schedule.dark_at,
+            Utc.with_ymd_and_hms(2024, 3, 20, 17, 30, 0).single()
+        );
+        assert!(schedule.raw_light_at < schedule.light_at);
+        assert!(schedule.raw_dark_at > schedule.dark_at);
+    }
+
+    #[test]
+    fn test_clamps_leave_times_inside_window() {
+        let mut config = london_config(SolarEvent::Sunrise, SolarEvent::Sunset);
+        config.schedule.earliest_light = Some(NaiveTime::from_hms_opt(4, 0, 0).unwrap());
+        config.schedule.latest_dark = Some(NaiveTime::from_hms_opt(22, 0, 0).unwrap());
+        let schedule = Schedule::resolve_in(&config, london_equinox(), &Utc).unwrap();
+        assert!(!schedule.is_adjusted());
+    }
+
+    #[test]
+    fn test_clamp_times_from_toml() {
+        let config: ScheduleConfig = toml::from_str(
+            "light_offset_minutes = 30\ndark_offset_minutes = -45\nearliest_light = \"07:00\"\nlatest_dark = \"20:30:00\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.light_offset_minutes, 30);
+        assert_eq!(config.dark_offset_minutes, -45);
+        assert_eq!(config.earliest_light, NaiveTime::from_hms_opt(7, 0, 0));
+        assert_eq!(config.latest_dark, NaiveTime::from_hms_opt(20, 30, 0));
+    }
+
+    #[test]
+    fn test_mode_at() {
+        let config = london_config(SolarEvent::CivilDawn, SolarEvent::CivilDusk);
+        let schedule = Schedule::resolve(&config, london_equinox()).unwrap();
+        let noon = Utc.with_ymd_and_hms(2024, 3, 20, 12, 0, 0).unwrap();
+        let midnight = Utc.with_ymd_and_hms(2024, 3, 20, 0, 0, 0).unwrap();
+        assert_eq!(schedule.mode_at(noon), ThemeMode::Light);
+        assert_eq!(schedule.mode_at(midnight), ThemeMode::Dark);
+        assert_eq!(
+            schedule.mode_at(schedule.light_at.unwrap()),
+            ThemeMode::Light
+        );
+        assert_eq!(schedule.mode_at(schedule.dark_at.unwrap()), ThemeMode::Dark);
+    }
+
+    #[test]
+    fn test_tromso_polar_day_stays_light() {
+        let (config, sun_times) = tromso(6);
+        let schedule = Schedule::resolve(&config, sun_times).unwrap();
+        assert_eq!(schedule.steady, Some(ThemeMode::Light));
+        assert!(schedule.light_at.is_none());
+        assert!(schedule.dark_at.is_none());
+
+        let midnight = Utc.with_ymd_and_hms(2024, 6, 21, 23, 0, 0).unwrap();
+        assert_eq!(schedule.mode_at(midnight), ThemeMode::Light);
+    }
+
+    #[test]
+    fn test_tromso_polar_night_stays_dark() {
+        let (config, sun_times) = tromso(12);
+        let schedule = Schedule::resolve(&config, sun_times).unwrap();
+        assert_eq!(schedule.steady, Some(ThemeMode::Dark));
+        assert!(schedule.dark_at.is_none());
+
+        let noon = Utc.with_ymd_and_hms(2024, 12, 21, 11, 0, 0).unwrap();
+        assert_eq!(schedule.mode_at(noon), ThemeMode::Dark);
+    }
+
+    #[test]
+    fn test_tromso_polar_fallback() {
+        let (mut config, sun_times) = tromso(12);
+        config.schedule.polar_fallback = Some(FixedTimes {
+            light: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            dark: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+        });
+        let schedule = Schedule::resolve_in(&config, sun_times, &Utc).unwrap();
+        assert!(schedule.uses_fallback());
+
+        let morning = Utc.with_ymd_and_hms(2024, 12, 21, 8, 0, 0).unwrap();
+        let noon = Utc.with_ymd_and_hms(2024, 12, 21, 12, 0, 0).unwrap();
+        let evening = Utc.with_ymd_and_hms(2024, 12, 21, 18, 0, 0).unwrap();
+        assert_eq!(schedule.mode_at(morning), ThemeMode::Dark);
+        assert_eq!(schedule.mode_at(noon), ThemeMode::Light);
+        assert_eq!(schedule.mode_at(evening), ThemeMode::Dark);
+    }
+
+    #[test]
+    fn test_threshold_not_reached() {
+        // London never gets astronomically dark around the summer solstice
+        let config = london_config(SolarEvent::Sunrise, SolarEvent::AstronomicalDusk);
+        let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let sun_times = SunTimes::compute(51.5074, -0.1278, date);
+        let schedule = Schedule::resolve(&config, sun_times).unwrap();
+        assert_eq!(schedule.steady, Some(ThemeMode::Light));
+    }
+
+    fn office_hours() -> FixedTimes {
+        FixedTimes {
+            light: NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+            dark: NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_fixed_schedule_ignores_sun() {
+        let date = NaiveDate::from_ymd_opt(2024, 12, 21).unwrap();
+        let schedule = Schedule::fixed_in(office_hours(), date, &Utc);
+        assert!(schedule.is_fixed());
+        assert!(schedule.is_custom());
+
+        let early = Utc.with_ymd_and_hms(2024, 12, 21, 7, 59, 0).unwrap();
+        let morning = Utc.with_ymd_and_hms(2024, 12, 21, 8, 0, 0).unwrap();
+        let evening = Utc.with_ymd_and_hms(2024, 12, 21, 18, 0, 0).unwrap();
+        assert_eq!(schedule.mode_at(early), ThemeMode::Dark);
+        assert_eq!(schedule.mode_at(morning), ThemeMode::Light);
+        assert_eq!(schedule.mode_at(evening), ThemeMode::Dark);
+    }
+
+    #[test]
+    fn test_fixed_schedule_needs_no_location() {
+        let mut config = Config::default();
+        config.schedule.mode = ScheduleMode::Fixed;
+        assert!(config.schedule.fixed_times().is_err());
+
+        config.schedule.fixed = Some(office_hours());
+        let today = Local::now().date_naive();
+        let schedule = Schedule::for_date(&config, today).unwrap();
+        assert!(schedule.is_fixed());
+        assert!(config.location.is_none());
+    }
+
+    #[test]
+    fn test_fallback_without_sun_times() {
+        // Sun mode with nowhere to calculate for
+        let mut config = Config::default();
+        let today = Local::now().date_naive();
+        assert!(Schedule::for_date(&config, today).is_err());
+
+        let schedule = Schedule::fallback(&config, today);
+        assert_eq!(schedule.source, Some(SunSource::Fixed));
+        assert_eq!(
+            schedule.light_at_local().unwrap().time(),
+            NaiveTime::from_hms_opt(7, 0, 0).unwrap()
+        );
+
+        config.schedule.fixed = Some(office_hours());
+        let schedule = Schedule::fallback(&config, today);
+        assert_eq!(
+            schedule.dark_at_local().unwrap().time(),
+            NaiveTime::from_hms_opt(18, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_fixed_schedule_rejects_inverted_times() {
+        let mut config = Config::default();
+        config.schedule.mode = ScheduleMode::Fixed;
+        config.schedule.fixed = Some(FixedTimes {
+            light: NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+            dark: NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+        });
+        assert!(config.schedule.fixed_times().is_err());
+    }
+
+    #[test]
+    fn test_fixed_schedule_from_toml() {
+        let config: ScheduleConfig =
+            toml::from_str("mode = \"fixed\"\n\n[fixed]\nlight = \"08:00\"\ndark = \"18:00\"\n")
+                .unwrap();
+        assert_eq!(config.mode, ScheduleMode::Fixed);
+        assert_eq!(config.fixed_times().unwrap(), office_hours());
+    }
+
+    #[test]
+    fn test_validate_config() {
+        let mut config = Config::default();
+        config.schedule.mode = ScheduleMode::Fixed;
+        assert!(config.validate().is_err());
+        config.schedule.fixed = Some(office_hours());
+        assert!(config.validate().is_ok());
+
+        // Sun rules need a location even when the default mode doesn't
+        config.schedule = weekly_rules();
+        assert!(config.validate().is_err());
+        config.location = london_config(SolarEvent::Sunrise, SolarEvent::Sunset).location;
+        assert!(config.validate().is_ok());
+
+        config.schedule.rules[0].light = NaiveTime::from_hms_opt(9, 0, 0);
+        assert!(config.validate().is_err());
+        config.schedule.rules[0].light = None;
+
+        for bad in [-1.0, f64::NAN, 45.0] {
+            config.stability.hysteresis_degrees = bad;
+            assert!(config.validate().is_err());
+        }
+        config.stability.hysteresis_degrees = 1.5;
+        assert!(config.validate().is_ok());
+
+        config.exceptions.push(Exception {
+            start: parse_local_datetime("2024-06-14", false).unwrap(),
+            end: parse_local_datetime("2024-06-10", true).unwrap(),
+            mode: ThemeMode::Dark,
+            note: None,
+        });
+        assert!(config.validate().is_err());
+    }
+
+    fn weekly_rules() -> ScheduleConfig {
+        toml::from_str(
+            "mode = \"sun\"\n\n\
+             [fixed]\nlight = \"08:00\"\ndark = \"18:00\"\n\n\
+             [[rules]]\ndays = [\"sun\"]\nmode = \"dark\"\n\n\
+             [[rules]]\ndays = [\"Saturday\"]\nmode = \"fixed\"\nlight = \"10:00\"\ndark = \"16:00\"\n\n\
+             [[rules]]\ndates = [\"2024-06-23\"]\nmode = \"light\"\n",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rules_select_mode_by_weekday() {
+        let schedule = weekly_rules();
+        let friday = NaiveDate::from_ymd_opt(2024, 6, 14).unwrap();
+        let saturday = NaiveDate::from_ymd_opt(2024, 6, 15).unwrap();
+        let sunday = NaiveDate::from_ymd_opt(2024, 6, 16).unwrap();
+        assert_eq!(schedule.mode_on(friday), ScheduleMode::Sun);
+        assert_eq!(schedule.mode_on(saturday), ScheduleMode::Fixed);
+        assert_eq!(schedule.mode_on(sunday), ScheduleMode::Dark);
+
+        let saturday_times = schedule.fixed_times_on(saturday).unwrap();
+        assert_eq!(
+            saturday_times.light,
+            NaiveTime::from_hms_opt(10, 0, 0).unwrap()
+        );
+        assert_eq!(schedule.fixed_times_on(friday).unwrap(), office_hours());
+    }
+
+    #[test]
+    fn test_date_rules_beat_weekday_rules() {
+        let schedule = weekly_rules();
+        let sunday = NaiveDate::from_ymd_opt(2024, 6, 23).unwrap();
+        assert_eq!(schedule.mode_on(sunday), ScheduleMode::Light);
+    }
+
+    #[test]
+    fn test_held_day_switches_at_midnight() {
+        let saturday = NaiveDate::from_ymd_opt(2024, 6, 15).unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 6, 15, 12, 0, 0).unwrap();
+        let transitions = transitions_after(&Utc, now, 2, |date| {
+            Ok(if date == saturday {
+                Schedule::fixed_in(office_hours(), date, &Utc)
+            } else {
+                Schedule::held_in(ScheduleMode::Light, ThemeMode::Light, date, &Utc)
+            })
+        })
+        .unwrap();
+
+        let sunday_start = Utc.with_ymd_and_hms(2024, 6, 16, 0, 0, 0).unwrap();
+        assert_eq!(transitions[0].mode, ThemeMode::Dark);
+        assert_eq!(transitions[1].at, sunday_start);
+        assert_eq!(transitions[1].mode, ThemeMode::Light);
+    }
+
+    #[test]
+    fn test_parse_exception_times() {
+        let start = parse_local_datetime("2024-06-10", false).unwrap();
+        let end = parse_local_datetime("2024-06-14", true).unwrap();
+        assert_eq!(start.to_string(), "2024-06-10 00:00:00");
+        assert_eq!(end.to_string(), "2024-06-15 00:00:00");
+
+        let at = parse_local_datetime("2024-06-10 09:30", true).unwrap();
+        assert_eq!(at.to_string(), "2024-06-10 09:30:00");
+        assert!(parse_local_datetime("next week", false).is_err());
+    }
+
+    #[test]
+    fn test_exception_overrides_schedule() {
+        let config = london_config(SolarEvent::Sunrise, SolarEvent::Sunset);
+        let exception: Exception = toml::from_str(
+            "start = \"2024-03-20 16:00\"\nend = \"2024-03-21 00:00\"\nmode = \"light\"\n",
+        )
+        .unwrap();
+
+        let schedule = |date| {
+            Ok(
+                Schedule::resolve_in(&config, SunTimes::compute(51.5074, -0.1278, date), &Utc)?
+                    .with_exceptions(std::slice::from_ref(&exception), &Utc),
+            )
+        };
+
+        let morning = Utc.with_ymd_and_hms(2024, 3, 20, 3, 0, 0).unwrap();
+        let transitions = transitions_after(&Utc, morning, 3, schedule).unwrap();
+
+        // Light at sunrise, held through the evening by the exception, then
+        // dark at midnight when it ends
+        let midnight = Utc.with_ymd_and_hms(2024, 3, 21, 0, 0, 0).unwrap();
+        assert_eq!(transitions[0].mode, ThemeMode::Light);
+        assert_eq!(transitions[1].at, midnight);
+        assert_eq!(transitions[1].mode, ThemeMode::Dark);
+        assert_eq!(transitions[2].mode, ThemeMode::Light);
+    }
+
+    fn london_phases() -> Config {
+        let mut config = london_config(SolarEvent::Sunrise, SolarEvent::Sunset);
+        let phases: toml::Value = toml::from_str(
+            "[[phases]]\nname = \"dawn\"\nat = \"civil_dawn\"\nmode = \"light\"\n\n\
+             [[phases]]\nname = \"day\"\nat = \"09:00\"\nmode = \"light\"\n\n\
+             [[phases]]\nname = \"golden\"\nat = \"golden_hour_start\"\nmode = \"light\"\n\n\
+             [[phases]]\nname = \"night\"\nat = \"sunset\"\nmode = \"dark\"\n",
+        )
+        .unwrap();
+        config.phases = phases["phases"].clone().try_into().unwrap();
+        config
+    }
+
+    /// A London day in UTC with the last phase of the day before carried
+    /// over, as `for_date` does
+    fn london_day(config: &Config, date: NaiveDate) -> Result<Schedule> {
+        let resolve =
+            |date| Schedule::resolve_in(config, SunTimes::compute(51.5074, -0.1278, date), &Utc);
+        Ok(resolve(date)?.after(&resolve(date - Duration::days(1))?))
+    }
+
+    #[test]
+    fn test_phase_start_from_toml() {
+        let config = london_phases();
+        assert_eq!(
+            config.phases[0].at,
+            PhaseStart::Event(SolarEvent::CivilDawn)
+        );
+        assert_eq!(
+            config.phases[1].at,
+            PhaseStart::Time(NaiveTime::from_hms_opt(9, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_phases_follow_the_day() {
+        let config = london_phases();
+        let schedule = london_day(&config, london_equinox().date).unwrap();
+        let names: Vec<&str> = schedule.phases.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["dawn", "day", "golden", "night"]);
+
+        let at = |h, m| Utc.with_ymd_and_hms(2024, 3, 20, h, m, 0).unwrap();
+        let phase = |h, m| schedule.phase_at(at(h, m)).unwrap().name.clone();
+        assert_eq!(phase(3, 0), "night");
+        assert_eq!(phase(5, 50), "dawn");
+        assert_eq!(phase(12, 0), "day");
+        assert_eq!(phase(18, 0), "golden");
+        assert_eq!(phase(21, 0), "night");
+
+        assert_eq!(schedule.mode_at(at(18, 0)), ThemeMode::Light);
+        assert_eq!(schedule.mode_at(at(3, 0)), ThemeMode::Dark);
+    }
+
+    #[test]
+    fn test_night_carries_over_the_last_phase_of_the_day_before() {
+        let mut config = london_phases();
+        let late: toml::Value =
+            toml::from_str("name = \"late\"\nat = \"20:00\"\nmode = \"dark\"\n").unwrap();
+        config.phases.push(late.try_into().unwrap());
+
+        // Sunset moves past 20:00 overnight, so the 24th ends late and the
+        // 25th ends at night
+        let date = NaiveDate::from_ymd_opt(2024, 5, 25).unwrap();
+        let last = |schedule: &Schedule| schedule.phases.last().unwrap().name.clone();
+        assert_eq!(
+            last(&london_day(&config, date - Duration::days(1)).unwrap()),
+            "late"
+        );
+        let schedule = london_day(&config, date).unwrap();
+        assert_eq!(last(&schedule), "night");
+
+        let before_dawn = Utc.with_ymd_and_hms(2024, 5, 25, 2, 0, 0).unwrap();
+        assert_eq!(schedule.phase_at(before_dawn).unwrap().name, "late");
+    }
+
+    #[test]
+    fn test_validate_phases() {
+        let mut config = london_phases();
+        assert!(config.validate().is_ok());
+
+        // An angle is crossed twice, so it needs saying which crossing
+        let low: toml::Value =
+            toml::from_str("name = \"low\"\nat = 3.0\nmode = \"light\"\n").unwrap();
+        config.phases.push(low.try_into().unwrap());
+        assert!(config.validate().is_err());
+        config.phases[4].direction = Some(SunDirection::Setting);
+        assert!(config.validate().is_ok());
+        config.phases[0].direction = Some(SunDirection::Rising);
+        assert!(config.validate().is_err());
+        config.phases[0].direction = None;
+
+        let mut theme = |name: &str| {
+            config.themes.ghostty.phases.clear();
+            config
+                .themes
+                .ghostty
+                .phases
+                .insert(name.into(), "rose-pine".into());
+            config.validate()
+        };
+        assert!(theme("golden").is_ok());
+        assert!(theme("goldne").is_err());
+    }
+
+    #[test]
+    fn test_light_phase_can_start_as_the_sun_sets() {
+        let mut config = london_phases();
+        let low: toml::Value =
+            toml::from_str("name = \"low\"\nat = 3.0\nmode = \"light\"\ndirection = \"setting\"\n")
+                .unwrap();
+        config.phases.push(low.try_into().unwrap());
+
+        let schedule = Schedule::resolve_in(&config, london_equinox(), &Utc).unwrap();
+        let names: Vec<&str> = schedule.phases.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["dawn", "day", "golden", "low", "night"]);
+    }
+
+    #[test]
+    fn test_phase_changes_are_transitions() {
+        let config = london_phases();
+        let morning = Utc.with_ymd_and_hms(2024, 3, 20, 3, 0, 0).unwrap();
+        let transitions =
+            transitions_after(&Utc, morning, 4, |date| london_day(&config, date)).unwrap();
+
+        let labels: Vec<String> = transitions.iter().map(|t| t.label()).collect();
+        assert_eq!(
+            labels,
+            [
+                "dawn (light)",
+                "day (light)",
+                "golden (light)",
+                "night (dark)"
+            ]
+        );
+    }
+
+    fn london_transitions(now: DateTime<Utc>, count: usize) -> Vec<Transition> {
+        let config = london_config(SolarEvent::Sunrise, SolarEvent::Sunset);
+        transitions_after(&Utc, now, count, |date| {
+            Schedule::resolve_in(&config, SunTimes::compute(51.5074, -0.1278, date), &Utc)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_next_transition_uses_tomorrows_sunrise() {
+        let evening = Utc.with_ymd_and_hms(2024, 3, 20, 21, 0, 0).unwrap();
+        let transitions = london_transitions(evening, 1);
+
+        let tomorrow = NaiveDate::from_ymd_opt(2024, 3, 21).unwrap();
+        let sunrise = SunTimes::compute(51.5074, -0.1278, tomorrow).sunrise;
+        assert_eq!(transitions.len(), 1);
+        assert_eq!(Some(transitions[0].at), sunrise);
+        assert_eq!(transitions[0].mode, ThemeMode::Light);
+
+        let today_sunrise = london_equinox().sunrise.unwrap();
+        assert_ne!(transitions[0].at, today_sunrise + Duration::days(1));
+    }
+
+    #[test]
+    fn test_next_transitions_alternate() {
+        let morning = Utc.with_ymd_and_hms(2024, 3, 20, 3, 0, 0).unwrap();
+        let transitions = london_transitions(morning, 6);
+        assert_eq!(transitions.len(), 6);
+
+        for pair in transitions.windows(2) {
+            assert!(pair[0].at < pair[1].at);
+            assert_eq!(pair[1].mode, pair[0].mode.opposite());
+        }
+        assert_eq!(transitions[0].mode, ThemeMode::Light);
+        assert_eq!(
+            transitions[5].at.date_naive(),
+            NaiveDate::from_ymd_opt(2024, 3, 22).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_no_transitions_during_polar_night() {
+        let (config, _) = tromso(12);
+        let now = Utc.with_ymd_and_hms(2024, 12, 21, 12, 0, 0).unwrap();
+        let transitions = transitions_after(&Utc, now, 1, |date| {
+            Schedule::resolve_in(&config, SunTimes::compute(69.6492, 18.9553, date), &Utc)
+        })
+        .unwrap();
+        assert!(transitions.is_empty());
+    }
+
+    #[test]
+    fn test_transition_into_polar_day() {
+        // Tromsø's last sunset before the midnight sun is in mid-May
+        let (config, _) = tromso(5);
+        let now = Utc.with_ymd_and_hms(2024, 5, 14, 12, 0, 0).unwrap();
+        let transitions = transitions_after(&Utc, now, 10, |date| {
+            Schedule::resolve_in(&config, SunTimes::compute(69.6492, 18.9553, date), &Utc)
+        })
+        .unwrap();
+
+        let last = transitions.last().unwrap();
+        assert_eq!(last.mode, ThemeMode::Light);
+        assert!(transitions.len() < 10);
+    }
+}
@@ -0,0 +1,341 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Name of the systemd user unit
+pub const SYSTEMD_UNIT: &str = "suntheme.service";
+
+/// Label of the launchd agent
+pub const LAUNCHD_LABEL: &str = "com.github.lucianlavric.suntheme";
+
+/// Where the systemd user unit is installed
+pub fn systemd_unit_path() -> Result<PathBuf> {
+    let config_dir = dirs::config_dir().context("Could not determine config directory")?;
+    Ok(config_dir.join("systemd").join("user").join(SYSTEMD_UNIT))
+}
+
+/// A systemd user unit running `exe` as a foreground daemon. `Type=notify`
+/// makes systemd wait for the daemon's READY message and show its status.
+pub fn systemd_unit(exe: &Path) -> String {
+    format!(
+        "[Unit]\n\
+         Description=suntheme automatic theme switching\n\
+         Documentation=https://github.com/lucianlavric/suntheme\n\
+         \n\
+         [Service]\n\
+         Type=notify\n\
+         ExecStart={} run --foreground\n\
+         Restart=on-failure\n\
+         RestartSec=10\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        quote_systemd(&exe.to_string_lossy())
+    )
+}
+
+/// Quote an ExecStart argument if it contains spaces, and escape the `%`
+/// specifiers and `$` variables systemd would expand even inside quotes
+fn quote_systemd(arg: &str) -> String {
+    let arg = arg.replace('%', "%%").replace('$', "$$");
+    if arg.contains(char::is_whitespace) {
+        format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        arg
+    }
+}
+
+/// Where the launchd agent plist is installed
+pub fn launchd_plist_path() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+    Ok(home
+        .join("Library")
+        .join("LaunchAgents")
+        .join(format!("{}.plist", LAUNCHD_LABEL)))
+}
+
+/// Whether the agent is installed, so launchd rather than `start`
+/// decides when the daemon runs
+pub fn launchd_agent_installed() -> bool {
+    cfg!(target_os = "macos") && launchd_plist_path().is_ok_and(|path| path.exists())
+}
+
+/// When launchd starts the daemon again after it exits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepAlive {
+    Never,
+    Always,
+    /// Only after a crash or an error exit, so `suntheme stop` sticks
+    OnFailure,
+}
+
+/// The settings suntheme writes to its launchd agent plist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LaunchAgent {
+    pub label: String,
+    pub program_arguments: Vec<String>,
+    pub run_at_load: bool,
+    pub keep_alive: KeepAlive,
+    pub log_path: Option<PathBuf>,
+}
+
+impl LaunchAgent {
+    /// An agent running `exe` as a foreground daemon at login, restarted if
+    /// it fails, with its output in `log_path`
+    pub fn new(exe: &Path, log_path: &Path) -> Self {
+        Self {
+            label: LAUNCHD_LABEL.to_string(),
+            program_arguments: vec![
+                exe.to_string_lossy().into_owned(),
+                "run".to_string(),
+                "--foreground".to_string(),
+            ],
+            run_at_load: true,
+            keep_alive: KeepAlive::OnFailure,
+            log_path: Some(log_path.to_path_buf()),
+        }
+    }
+
+    pub fn to_plist(&self) -> String {
+        let mut plist = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+             \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+             <plist version=\"1.0\">\n<dict>\n",
+        );
+        plist.push_str(&format!(
+            "    <key>Label</key>\n    <string>{}</string>\n",
+            escape_xml(&self.label)
+        ));
+        plist.push_str("    <key>ProgramArguments</key>\n    <array>\n");
+        for arg in &self.program_arguments {
+            plist.push_str(&format!("        <string>{}</string>\n", escape_xml(arg)));
+        }
+        plist.push_str("    </array>\n");
+        plist.push_str(&format!(
+            "    <key>RunAtLoad</key>\n    <{}/>\n",
+            self.run_at_load
+        ));
+        plist.push_str("    <key>KeepAlive</key>\n");
+        plist.push_str(match self.keep_alive {
+            KeepAlive::Never => "    <false/>\n",
+            KeepAlive::Always => "    <true/>\n",
+            KeepAlive::OnFailure => {
+                "    <dict>\n        <key>SuccessfulExit</key>\n        <false/>\n    </dict>\n"
+            }
+        });
+        if let Some(log_path) = &self.log_path {
+            let log_path = escape_xml(&log_path.to_string_lossy());
+            for key in ["StandardOutPath", "StandardErrorPath"] {
+                plist.push_str(&format!(
+                    "    <key>{}</key>\n    <string>{}</string>\n",
+                    key, log_path
+                ));
+            }
+        }
+        plist.push_str("</dict>\n</plist>\n");
+        plist
+    }
+
+    /// Read back an agent plist. Only the keys suntheme writes are
+    /// understood; others are skipped.
+    pub fn parse(content: &str) -> Result<Self> {
+        let body = content
+            .split_once("<dict>")
+            .and_then(|(_, rest)| rest.rsplit_once("</dict>"))
+            .map(|(body, _)| body)
+            .context("Not a launchd plist: missing <dict>")?;
+
+        let mut agent = Self {
+            label: String::new(),
+            program_arguments: Vec::new(),
+            run_at_load: false,
+            keep_alive: KeepAlive::Never,
+            log_path: None,
+        };
+
+        let mut rest = body;
+        while let Some((key, after)) = next_element(rest, "key") {
+            let after = after.trim_start();
+            rest = match key.as_str() {
+                "Label" => {
+                    let (value, after) =
+                        next_element(after, "string").context("Label must be a string")?;
+                    agent.label = value;
+                    after
+                }
+                "ProgramArguments" => {
+                    let (array, after) = after
+                        .strip_prefix("<array>")
+                        .and_then(|a| a.split_once("</array>"))
+                        .context("ProgramArguments must be an array")?;
+                    let mut items = array;
+                    while let Some((arg, next)) = next_element(items, "string") {
+                        agent.program_arguments.push(arg);
+                        items = next;
+                    }
+                    after
+                }
+                "RunAtLoad" => {
+                    let (value, after) =
+                        parse_bool(after).context("RunAtLoad must be true or false")?;
+                    agent.run_at_load = value;
+                    after
+                }
+                "KeepAlive" => {
+                    let (value, after) = parse_keep_alive(after).context(
+                        "KeepAlive must be true, false or a dict with SuccessfulExit false",
+                    )?;
+                    agent.keep_alive = value;
+                    after
+                }
+                "StandardOutPath" | "StandardErrorPath" => {
+                    let (value, after) = next_element(after, "string")
+                        .with_context(|| format!("{} must be a string", key))?;
+                    agent.log_path = Some(PathBuf::from(value));
+                    after
+                }
+                _ => after,
+            };
+        }
+
+        if agent.label.is_empty() {
+            anyhow::bail!("The launchd plist has no Label");
+        }
+        Ok(agent)
+    }
+}
+
+/// Find the next `<tag>text</tag>`, returning its unescaped text and the
+/// rest of the input
+fn next_element<'a>(input: &'a str, tag: &str) -> Option<(String, &'a str)> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = input.find(&open)? + open.len();
+    let end = start + input[start..].find(&close)?;
+    Some((
+        unescape_xml(&input[start..end]),
+        &input[end + close.len()..],
+    ))
+}
+
+fn parse_bool(input: &str) -> Option<(bool, &str)> {
+    if let Some(rest) = input.strip_prefix("<true/>") {
+        Some((true, rest))
+    } else {
+        input.strip_prefix("<false/>").map(|rest| (false, rest))
+    }
+}
+
+/// `<true/>`, `<false/>`, or the `<dict>` form restarting only on failure
+fn parse_keep_alive(input: &str) -> Option<(KeepAlive, &str)> {
+    if let Some((value, rest)) = parse_bool(input) {
+        let keep_alive = if value {
+            KeepAlive::Always
+        } else {
+            KeepAlive::Never
+        };
+        return Some((keep_alive, rest));
+    }
+    let (dict, rest) = input
+        .strip_prefix("<dict>")
+        .and_then(|d| d.split_once("</dict>"))?;
+    let (key, value) = next_element(dict, "key")?;
+    match (key.as_str(), parse_bool(value.trim())?) {
+        ("SuccessfulExit", (false, "")) => Some((KeepAlive::OnFailure, rest)),
+        _ => None,
+    }
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape_xml(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_systemd_unit() {
+        let unit = systemd_unit(Path::new("/usr/local/bin/suntheme"));
+        assert!(unit.contains("Type=notify\n"));
+        assert!(unit.contains("ExecStart=/usr/local/bin/suntheme run --foreground\n"));
+        assert!(unit.contains("Restart=on-failure\n"));
+        assert!(unit.contains("WantedBy=default.target\n"));
+
+        let unit = systemd_unit(Path::new("/home/me/my bin/suntheme"));
+        assert!(unit.contains("ExecStart=\"/home/me/my bin/suntheme\" run --foreground\n"));
+    }
+
+    #[test]
+    fn test_systemd_unit_escapes_specifiers() {
+        let unit = systemd_unit(Path::new("/opt/100%/$HOME/suntheme"));
+        assert!(unit.contains("ExecStart=/opt/100%%/$$HOME/suntheme run --foreground\n"));
+
+        let unit = systemd_unit(Path::new("/home/me/50% off/suntheme"));
+        assert!(unit.contains("ExecStart=\"/home/me/50%% off/suntheme\" run --foreground\n"));
+    }
+
+    #[test]
+    fn test_launch_agent_plist() {
+        let agent = LaunchAgent::new(
+            Path::new("/opt/homebrew/bin/suntheme"),
+            Path::new("/Users/me/Library/Application Support/suntheme/daemon.log"),
+        );
+        let plist = agent.to_plist();
+        assert!(plist
+            .contains("<key>Label</key>\n    <string>com.github.lucianlavric.suntheme</string>"));
+        assert!(plist.contains(
+            "<key>KeepAlive</key>\n    <dict>\n        <key>SuccessfulExit</key>\n        <false/>\n    </dict>"
+        ));
+        assert!(plist.contains("<string>--foreground</string>"));
+        assert!(plist.contains("<key>StandardErrorPath</key>"));
+
+        assert_eq!(LaunchAgent::parse(&plist).unwrap(), agent);
+    }
+
+    #[test]
+    fn test_launch_agent_escapes_paths() {
+        let agent = LaunchAgent::new(
+            Path::new("/Users/me/R&D <tools>/suntheme"),
+            Path::new("/tmp/log"),
+        );
+        let plist = agent.to_plist();
+        assert!(plist.contains("R&amp;D &lt;tools&gt;"));
+        assert_eq!(
+            LaunchAgent::parse(&plist).unwrap().program_arguments[0],
+            "/Users/me/R&D <tools>/suntheme"
+        );
+    }
+
+    #[test]
+    fn test_launch_agent_parse_errors() {
+        assert!(LaunchAgent::parse("not a plist").is_err());
+        assert!(
+            LaunchAgent::parse("<plist><dict><key>KeepAlive</key><true/></dict></plist>").is_err()
+        );
+        assert!(LaunchAgent::parse(
+            "<plist><dict><key>Label</key><string>x</string><key>KeepAlive</key><string>yes</string></dict></plist>"
+        )
+        .is_err());
+        assert!(LaunchAgent::parse(
+            "<plist><dict><key>Label</key><string>x</string><key>KeepAlive</key><dict><key>Crashed</key><true/></dict></dict></plist>"
+        )
+        .is_err());
+        let agent = LaunchAgent::parse(
+            "<plist><dict><key>Label</key><string>x</string><key>KeepAlive</key><true/></dict></plist>",
+        )
+        .unwrap();
+        assert_eq!(agent.keep_alive, KeepAlive::Always);
+    }
+}
@@ -0,0 +1,250 @@
+// Offline solar position calculations based on NOAA's solar calculator
+// (https://gml.noaa.gov/grad/solcalc/calcdetails.html).
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+/// Solar elevation of the sun's centre at sunrise/sunset, accounting for
+/// atmospheric refraction and the apparent radius of the solar disc.
+pub const SUNRISE_ELEVATION: f64 = -0.833;
+
+/// Outcome of looking for the moment the sun crosses a given elevation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crossing {
+    /// The sun crosses the elevation at this instant
+    At(DateTime<Utc>),
+    /// The sun stays above the elevation all day
+    AlwaysAbove,
+    /// The sun stays below the elevation all day
+    AlwaysBelow,
+}
+
+/// Julian day for a UTC instant
+fn julian_day(instant: DateTime<Utc>) -> f64 {
+    instant.timestamp() as f64 / 86400.0 + 2_440_587.5
+}
+
+/// Equation of time (minutes) and solar declination (degrees) at an instant
+fn sun_parameters(instant: DateTime<Utc>) -> (f64, f64) {
+    let t = (julian_day(instant) - 2_451_545.0) / 36525.0;
+
+    let mean_long = (280.46646 + t * (36000.76983 + t * 0.0003032)).rem_euclid(360.0);
+    let mean_anom = 357.52911 + t * (35999.05029 - 0.0001537 * t);
+    let eccent = 0.016708634 - t * (0.000042037 + 0.0000001267 * t);
+
+    let m = mean_anom.to_radians();
+    let eq_of_center = m.sin() * (1.914602 - t * (0.004817 + 0.000014 * t))
+        + (2.0 * m).sin() * (0.019993 - 0.000101 * t)
+        + (3.0 * m).sin() * 0.000289;
+
+    let omega = (125.04 - 1934.136 * t).to_radians();
+    let app_long = mean_long + eq_of_center - 0.00569 - 0.00478 * omega.sin();
+
+    let mean_obliq =
+        23.0 + (26.0 + (21.448 - t * (46.815 + t * (0.00059 - t * 0.001813))) / 60.0) / 60.0;
+    let obliq = (mean_obliq + 0.00256 * omega.cos()).to_radians();
+
+    let declination = (obliq.sin() * app_long.to_radians().sin())
+        .asin()
+        .to_degrees();
+
+    let y = (obliq / 2.0).tan().powi(2);
+    let l0 = mean_long.to_radians();
+    let eq_of_time = 4.0
+        * (y * (2.0 * l0).sin() - 2.0 * eccent * m.sin()
+            + 4.0 * eccent * y * m.sin() * (2.0 * l0).cos()
+            - 0.5 * y * y * (4.0 * l0).sin()
+            - 1.25 * eccent * eccent * (2.0 * m).sin())
+        .to_degrees();
+
+    (eq_of_time, declination)
+}
+
+fn utc_midnight(date: NaiveDate) -> DateTime<Utc> {
+    date.and_hms_opt(0, 0, 0).unwrap().and_utc()
+}
+
+fn minutes_after(date: NaiveDate, minutes: f64) -> DateTime<Utc> {
+    utc_midnight(date) + Duration::milliseconds((minutes * 60_000.0).round() as i64)
+}
+
+/// Solar noon (UTC) for the given date and longitude
+pub fn solar_noon(date: NaiveDate, longitude: f64) -> DateTime<Utc> {
+    let mut noon = minutes_after(date, 720.0 - 4.0 * longitude);
+    for _ in 0..2 {
+        let (eq_of_time, _) = sun_parameters(noon);
+        noon = minutes_after(date, 720.0 - 4.0 * longitude - eq_of_time);
+    }
+    noon
+}
+
+/// The sun's elevation in degrees at `instant`, seen from the given
+/// location, without refraction
+pub fn elevation(instant: DateTime<Utc>, latitude: f64, longitude: f64) -> f64 {
+    let (eq_of_time, declination) = sun_parameters(instant);
+    let minutes =
+        (instant - utc_midnight(instant.date_naive())).num_milliseconds() as f64 / 60_000.0;
+    let hour_angle = ((minutes + eq_of_time + 4.0 * longitude) / 4.0 - 180.0).to_radians();
+    let (lat, decl) = (latitude.to_radians(), declination.to_radians());
+    (lat.sin() * decl.sin() + lat.cos() * decl.cos() * hour_angle.cos())
+        .asin()
+        .to_degrees()
+}
+
+/// Find when the sun crosses `elevation` degrees on `date`, either on the
+/// way up (`rising`) or on the way down. The date is interpreted as the
+/// day of solar noon at the given longitude.
+pub fn crossing(
+    date: NaiveDate,
+    latitude: f64,
+    longitude: f64,
+    elevation: f64,
+    rising: bool,
+) -> Crossing {
+    let lat = latitude.to_radians();
+    let sign = if rising { -1.0 } else { 1.0 };
+
+    // Start from solar noon and refine using the sun's position at the
+    // estimated event time, since declination drifts through the day.
+    let mut estimate = solar_noon(date, longitude);
+    for _ in 0..3 {
+        let (eq_of_time, declination) = sun_parameters(estimate);
+        let decl = declination.to_radians();
+        let cos_hour_angle =
+            (elevation.to_radians().sin() - lat.sin() * decl.sin()) / (lat.cos() * decl.cos());
+
+        if cos_hour_angle > 1.0 {
+            return Crossing::AlwaysBelow;
+        }
+        if cos_hour_angle < -1.0 {
+            return Crossing::AlwaysAbove;
+        }
+
+        let hour_angle = cos_hour_angle.acos().to_degrees();
+        let minutes = 720.0 - 4.0 * longitude - eq_of_time + sign * 4.0 * hour_angle;
+        estimate = minutes_after(date, minutes);
+    }
+
+    Crossing::At(estimate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn utc(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    fn assert_close(actual: Crossing, expected: DateTime<Utc>) {
+        match actual {
+            Crossing::At(t) => {
+                let diff = (t - expected).num_seconds().abs();
+                assert!(
+                    diff <= 60,
+                    "expected {}, got {} ({}s off)",
+                    expected,
+                    t,
+                    diff
+                );
+            }
+            other => panic!("expected a crossing at {}, got {:?}", expected, other),
+        }
+    }
+
+    #[test]
+    fn test_london_summer_solstice() {
+        let (lat, lon) = (51.5074, -0.1278);
+        let day = date(2024, 6, 21);
+        assert_close(
+            crossing(day, lat, lon, SUNRISE_ELEVATION, true),
+            utc(2024, 6, 21, 3, 43),
+        );
+        assert_close(
+            crossing(day, lat, lon, SUNRISE_ELEVATION, false),
+            utc(2024, 6, 21, 20, 21),
+        );
+    }
+
+    #[test]
+    fn test_london_winter_solstice() {
+        let (lat, lon) = (51.5074, -0.1278);
+        let day = date(2024, 12, 21);
+        assert_close(
+            crossing(day, lat, lon, SUNRISE_ELEVATION, true),
+            utc(2024, 12, 21, 8, 4),
+        );
+        assert_close(
+            crossing(day, lat, lon, SUNRISE_ELEVATION, false),
+            utc(2024, 12, 21, 15, 53),
+        );
+    }
+
+    #[test]
+    fn test_new_york_equinox() {
+        let (lat, lon) = (40.7128, -74.0060);
+        let day = date(2024, 3, 20);
+        assert_close(
+            crossing(day, lat, lon, SUNRISE_ELEVATION, true),
+            utc(2024, 3, 20, 10, 58),
+        );
+        assert_close(
+            crossing(day, lat, lon, SUNRISE_ELEVATION, false),
+            utc(2024, 3, 20, 23, 9),
+        );
+    }
+
+    #[test]
+    fn test_sydney_southern_summer() {
+        let (lat, lon) = (-33.8688, 151.2093);
+        // Sydney is east of Greenwich, so local sunrise falls on the previous UTC day
+        let day = date(2024, 12, 21);
+        assert_close(
+            crossing(day, lat, lon, SUNRISE_ELEVATION, true),
+            utc(2024, 12, 20, 18, 41),
+        );
+        assert_close(
+            crossing(day, lat, lon, SUNRISE_ELEVATION, false),
+            utc(2024, 12, 21, 9, 5),
+        );
+    }
+
+    #[test]
+    fn test_solar_noon_greenwich() {
+        // Equation of time is about +14 minutes in early November
+        let noon = solar_noon(date(2024, 11, 3), 0.0);
+        let expected = utc(2024, 11, 3, 11, 44);
+        assert!((noon - expected).num_seconds().abs() <= 60);
+    }
+
+    #[test]
+    fn test_elevation_at_crossings() {
+        let (lat, lon) = (51.5074, -0.1278);
+        let day = date(2024, 3, 20);
+        for (target, rising) in [(SUNRISE_ELEVATION, true), (-6.0, false), (10.0, true)] {
+            let Crossing::At(at) = crossing(day, lat, lon, target, rising) else {
+                panic!("no crossing of {}°", target);
+            };
+            let found = elevation(at, lat, lon);
+            assert!((found - target).abs() < 0.05, "{} vs {}", found, target);
+        }
+        assert!(elevation(solar_noon(day, lon), lat, lon) > 38.0);
+    }
+
+    #[test]
+    fn test_polar_crossings() {
+        let (lat, lon) = (69.6492, 18.9553);
+        assert_eq!(
+            crossing(date(2024, 6, 21), lat, lon, SUNRISE_ELEVATION, true),
+            Crossing::AlwaysAbove
+        );
+        assert_eq!(
+            crossing(date(2024, 12, 21), lat, lon, SUNRISE_ELEVATION, false),
+            Crossing::AlwaysBelow
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use chrono::{DateTime, Duration, Local, Utc};
+
+use crate::config::StabilityConfig;
+use crate::sun_times::ThemeMode;
+
+/// How often to look at the sun again while a switch back is held for
+/// hysteresis
+const BAND_RECHECK_MINUTES: i64 = 5;
+
+/// A switch the daemon made where the sun crossed a threshold
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crossed {
+    pub mode: ThemeMode,
+    /// The switching time the schedule gave
+    pub at: DateTime<Utc>,
+    /// The sun's elevation then
+    pub elevation: f64,
+}
+
+/// Why an automatic switch was held back, and until when
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suppressed {
+    pub reason: String,
+    pub until: DateTime<Utc>,
+}
+
+/// Tracks recent switches so the daemon can hold back ones that come too
+/// soon after the last
+#[derive(Debug, Clone)]
+pub struct Stability {
+    settings: StabilityConfig,
+    last_switch: Option<DateTime<Utc>>,
+    last_manual: Option<DateTime<Utc>>,
+    last_crossed: Option<Crossed>,
+}
+
+impl Stability {
+    pub fn new(settings: StabilityConfig) -> Self {
+        Self {
+            settings,
+            last_switch: None,
+            last_manual: None,
+            last_crossed: None,
+        }
+    }
+
+    /// Use new limits, keeping the record of recent switches
+    pub fn configure(&mut self, settings: StabilityConfig) {
+        self.settings = settings;
+    }
+
+    /// Note an automatic switch made at `at`, and the threshold crossing
+    /// it followed if it followed one
+    pub fn record_switch(&mut self, at: DateTime<Utc>, crossed: Option<Crossed>) {
+        self.last_switch = Some(at);
+        self.last_crossed = crossed;
+    }
+
+    /// Note a manual `set` or `toggle` made at `at`
+    pub fn record_manual(&mut self, at: DateTime<Utc>) {
+        self.last_manual = self.last_manual.max(Some(at));
+    }
+
+    /// Whether an automatic switch at `now` has to wait
+    pub fn check(&self, now: DateTime<Utc>) -> Option<Suppressed> {
+        let min_switch = Duration::minutes(self.settings.min_switch_minutes as i64);
+        if let Some(last) = self.last_switch.filter(|at| *at + min_switch > now) {
+            return Some(Suppressed {
+                reason: format!(
+                    "last switch was at {}, less than {} min ago",
+                    last.with_timezone(&Local).format("%H:%M:%S"),
+                    self.settings.min_switch_minutes
+                ),
+                until: last + min_switch,
+            });
+        }
+
+        let grace = Duration::minutes(self.settings.manual_grace_minutes as i64);
+        if let Some(manual) = self.last_manual.filter(|at| *at + grace > now) {
+            return Some(Suppressed {
+                reason: format!(
+                    "manual change at {}, less than {} min ago",
+                    manual.with_timezone(&Local).format("%H:%M:%S"),
+                    self.settings.manual_grace_minutes
+                ),
+                until: manual + grace,
+            });
+        }
+
+        None
+    }
+
+    /// Whether switching to `mode` at `now` would flip back across the
+    /// threshold last crossed while the sun is still within
+    /// `hysteresis_degrees` of it. Once the sun has moved that far past
+    /// the threshold either way, switches go ahead at their usual times.
+    /// `elevation` gives the sun's elevation at an instant.
+    pub fn check_band(
+        &self,
+        mode: ThemeMode,
+        now: DateTime<Utc>,
+        elevation: impl Fn(DateTime<Utc>) -> f64,
+    ) -> Option<Suppressed> {
+        let band = self.settings.hysteresis_degrees;
+        let crossed = self.last_crossed.filter(|c| band > 0.0 && c.mode != mode)?;
+        // Degrees past the threshold in the direction of the last switch
+        let past = |at| match crossed.mode {
+            ThemeMode::Light => elevation(at) - crossed.elevation,
+            ThemeMode::Dark => crossed.elevation - elevation(at),
+        };
+
+        // The sun went well past the threshold, so this is a new crossing
+        // rather than a wobble
+        let step = Duration::minutes(BAND_RECHECK_MINUTES);
+        let mut at = crossed.at;
+        while at < now {
+            if past(at) >= band {
+                return None;
+            }
+            at += step;
+        }
+        if past(now) >= band || past(now) <= -band {
+            return None;
+        }
+
+        Some(Suppressed {
+            reason: format!(
+                "the sun is within {}° of the {:.1}° crossed at {}",
+                band,
+                crossed.elevation,
+                crossed.at.with_timezone(&Local).format("%H:%M:%S")
+            ),
+            until: now + step,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(h: u32, m: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 20, h, m, 0).unwrap()
+    }
+
+    fn crossed(mode: ThemeMode, at: DateTime<Utc>) -> Option<Crossed> {
+        Some(Crossed {
+            mode,
+            at,
+            elevation: 0.0,
+        })
+    }
+
+    #[test]
+    fn test_no_limits_by_default() {
+        let mut stability = Stability::new(StabilityConfig::default());
+        stability.record_switch(at(18, 0), crossed(ThemeMode::Dark, at(18, 0)));
+        stability.record_manual(at(18, 0));
+        assert_eq!(stability.check(at(18, 0)), None);
+        assert_eq!(
+            stability.check_band(ThemeMode::Light, at(18, 5), |_| 0.1),
+            None
+        );
+    }
+
+    #[test]
+    fn test_min_switch_time() {
+        let mut stability = Stability::new(StabilityConfig {
+            min_switch_minutes: 30,
+            ..StabilityConfig::default()
+        });
+        assert_eq!(stability.check(at(18, 0)), None);
+
+        stability.record_switch(at(18, 0), None);
+        let suppressed = stability.check(at(18, 10)).unwrap();
+        assert_eq!(suppressed.until, at(18, 30));
+        assert_eq!(stability.check(at(18, 30)), None);
+    }
+
+    #[test]
+    fn test_manual_grace() {
+        let mut stability = Stability::new(StabilityConfig {
+            manual_grace_minutes: 15,
+            ..StabilityConfig::default()
+        });
+        stability.record_manual(at(17, 55));
+        stability.record_manual(at(17, 50));
+
+        let suppressed = stability.check(at(18, 0)).unwrap();
+        assert_eq!(suppressed.until, at(18, 10));
+        assert!(suppressed.reason.contains("manual"));
+        assert_eq!(stability.check(at(18, 10)), None);
+    }
+
+    #[test]
+    fn test_no_flip_back_while_the_sun_wobbles() {
+        let mut stability = Stability::new(StabilityConfig {
+            hysteresis_degrees: 1.0,
+            ..StabilityConfig::default()
+        });
+        // The sun grazes the threshold: just above it until 7:00, then
+        // just below, then well below from 8:00
+        let wobble = |t: DateTime<Utc>| match t {
+            t if t < at(7, 0) => 0.4,
+            t if t < at(8, 0) => -0.6,
+            _ => -1.5,
+        };
+        stability.record_switch(at(6, 0), crossed(ThemeMode::Light, at(6, 0)));
+
+        let held = stability
+            .check_band(ThemeMode::Dark, at(7, 10), wobble)
+            .unwrap();
+        assert!(held.reason.contains("within 1°"), "{}", held.reason);
+        assert_eq!(held.until, at(7, 15));
+        assert_eq!(
+            stability.check_band(ThemeMode::Light, at(7, 10), wobble),
+            None
+        );
+        assert_eq!(
+            stability.check_band(ThemeMode::Dark, at(8, 0), wobble),
+            None
+        );
+    }
+
+    #[test]
+    fn test_band_lets_the_next_crossing_through() {
+        let mut stability = Stability::new(StabilityConfig {
+            hysteresis_degrees: 1.0,
+            ..StabilityConfig::default()
+        });
+        // A normal day: the sun climbs well past the threshold after
+        // sunrise and comes back to it at sunset
+        let day = |t: DateTime<Utc>| if t < at(18, 0) { 30.0 } else { 0.0 };
+        stability.record_switch(at(6, 0), crossed(ThemeMode::Light, at(6, 0)));
+        assert_eq!(stability.check_band(ThemeMode::Dark, at(18, 0), day), None);
+    }
+}
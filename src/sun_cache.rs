@@ -0,0 +1,204 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::sun_times::SunTimes;
+
+/// How many past days of sun times to keep around as a fallback
+const KEEP_DAYS_BEHIND: i64 = 7;
+
+/// Locations closer than this (in degrees, roughly 10 m) share cache entries
+const LOCATION_EPSILON: f64 = 1e-4;
+
+/// On-disk cache of sun times, keyed by provider, location and date
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SunCache {
+    pub entries: Vec<CacheEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub provider: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub sun_times: SunTimes,
+    pub cached_at: DateTime<Utc>,
+}
+
+impl CacheEntry {
+    fn matches(&self, provider: &str, latitude: f64, longitude: f64) -> bool {
+        self.provider == provider
+            && (self.latitude - latitude).abs() < LOCATION_EPSILON
+            && (self.longitude - longitude).abs() < LOCATION_EPSILON
+    }
+}
+
+impl SunCache {
+    pub fn path() -> Result<PathBuf> {
+        Ok(Config::cache_dir()?.join("sun_times.json"))
+    }
+
+    /// Load the cache, treating a missing or unreadable file as empty
+    pub fn load() -> Self {
+        Self::path()
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        let dir = path.parent().unwrap();
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create cache directory {:?}", dir))?;
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write cache to {:?}", path))?;
+        Ok(())
+    }
+
+    pub fn clear() -> Result<bool> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(false);
+        }
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {:?}", path))?;
+        Ok(true)
+    }
+
+    pub fn get(
+        &self,
+        provider: &str,
+        latitude: f64,
+        longitude: f64,
+        date: NaiveDate,
+    ) -> Option<&SunTimes> {
+        self.entries
+            .iter()
+            .find(|e| e.sun_times.date == date && e.matches(provider, latitude, longitude))
+            .map(|e| &e.sun_times)
+    }
+
+    pub fn insert(&mut self, provider: &str, latitude: f64, longitude: f64, sun_times: SunTimes) {
+        let date = sun_times.date;
+        self.entries
+            .retain(|e| !(e.sun_times.date == date && e.matches(provider, latitude, longitude)));
+        self.entries.push(CacheEntry {
+            provider: provider.to_string(),
+            latitude,
+            longitude,
+            sun_times,
+            cached_at: Utc::now(),
+        });
+        self.entries.sort_by_key(|e| e.sun_times.date);
+    }
+
+    /// The entry closest to `date` within `max_days` of it, for when the
+    /// provider can't be reached. Earlier days win ties.
+    pub fn nearest(
+        &self,
+        provider: &str,
+        latitude: f64,
+        longitude: f64,
+        date: NaiveDate,
+        max_days: i64,
+    ) -> Option<&SunTimes> {
+        self.entries
+            .iter()
+            .filter(|e| e.matches(provider, latitude, longitude))
+            .map(|e| &e.sun_times)
+            .filter(|t| (t.date - date).num_days().abs() <= max_days)
+            .min_by_key(|t| ((t.date - date).num_days().abs(), t.date > date))
+    }
+
+    /// Drop entries more than a week older than `today`
+    pub fn prune(&mut self, today: NaiveDate) {
+        let cutoff = today - Duration::days(KEEP_DAYS_BEHIND);
+        self.entries.retain(|e| e.sun_times.date >= cutoff);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sun_times::DayKind;
+
+    fn sun_times(date: NaiveDate) -> SunTimes {
+        SunTimes {
+            sunrise: date.and_hms_opt(6, 0, 0).map(|t| t.and_utc()),
+            sunset: date.and_hms_opt(18, 0, 0).map(|t| t.and_utc()),
+            date,
+            kind: DayKind::Normal,
+        }
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, day).unwrap()
+    }
+
+    #[test]
+    fn test_get_matches_provider_location_and_date() {
+        let mut cache = SunCache::default();
+        cache.insert("open-meteo", 51.5074, -0.1278, sun_times(date(10)));
+
+        assert!(cache
+            .get("open-meteo", 51.5074, -0.1278, date(10))
+            .is_some());
+        assert!(cache
+            .get("open-meteo", 51.50741, -0.12779, date(10))
+            .is_some());
+        assert!(cache
+            .get("open-meteo", 51.5074, -0.1278, date(11))
+            .is_none());
+        assert!(cache
+            .get("sunrise-sunset", 51.5074, -0.1278, date(10))
+            .is_none());
+        assert!(cache.get("open-meteo", 48.8566, 2.3522, date(10)).is_none());
+    }
+
+    #[test]
+    fn test_insert_replaces_existing_entry() {
+        let mut cache = SunCache::default();
+        cache.insert("open-meteo", 51.5074, -0.1278, sun_times(date(10)));
+        cache.insert("open-meteo", 51.5074, -0.1278, sun_times(date(10)));
+        cache.insert("open-meteo", 48.8566, 2.3522, sun_times(date(10)));
+        assert_eq!(cache.entries.len(), 2);
+    }
+
+    #[test]
+    fn test_nearest_prefers_closest_earlier_day() {
+        let mut cache = SunCache::default();
+        for day in [3, 8, 12] {
+            cache.insert("open-meteo", 51.5074, -0.1278, sun_times(date(day)));
+        }
+        cache.insert("open-meteo", 48.8566, 2.3522, sun_times(date(10)));
+
+        let nearest = |day| {
+            cache
+                .nearest("open-meteo", 51.5074, -0.1278, date(day), 7)
+                .map(|t| t.date)
+        };
+        assert_eq!(nearest(10), Some(date(8)));
+        assert_eq!(nearest(11), Some(date(12)));
+        assert_eq!(nearest(1), Some(date(3)));
+        assert_eq!(nearest(20), None);
+        assert!(cache
+            .nearest("sunrise-sunset", 51.5074, -0.1278, date(10), 7)
+            .is_none());
+    }
+
+    #[test]
+    fn test_prune_keeps_recent_and_future_days() {
+        let mut cache = SunCache::default();
+        for day in 1..=20 {
+            cache.insert("open-meteo", 51.5074, -0.1278, sun_times(date(day)));
+        }
+        cache.prune(date(15));
+        assert_eq!(cache.entries.first().unwrap().sun_times.date, date(8));
+        assert_eq!(cache.entries.last().unwrap().sun_times.date, date(20));
+    }
+}
@@ -1,9 +1,14 @@
 use anyhow::{Context, Result};
-use chrono::{DateTime, Local, NaiveDate, Utc};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
 
-use crate::config::Config;
+use crate::config::{Config, ProviderConfig, ProviderKind};
+use crate::solar::{self, Crossing};
+use crate::sun_cache::SunCache;
 
 #[derive(Debug, Clone)]
 pub struct GeocodedLocation {
@@ -27,6 +32,7 @@ pub fn geocode_location(query: &str) -> Result<Vec<GeocodedLocation>> {
 
     let client = reqwest::blocking::Client::builder()
         .user_agent("suntheme/0.1.0")
+        .timeout(HTTP_TIMEOUT)
         .build()?;
 
     let results: Vec<NominatimResult> = client
@@ -56,11 +62,186 @@ pub fn geocode_location(query: &str) -> Result<Vec<GeocodedLocation>> {
     Ok(locations)
 }
 
+/// Whether the sun rises and sets on a given day
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DayKind {
+    #[default]
+    Normal,
+    /// The sun stays above the horizon all day (midnight sun)
+    PolarDay,
+    /// The sun stays below the horizon all day
+    PolarNight,
+}
+
+/// Sunrise and sunset for one day. Both are `None` during polar day and
+/// polar night.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SunTimes {
-    pub sunrise: DateTime<Utc>,
-    pub sunset: DateTime<Utc>,
+    pub sunrise: Option<DateTime<Utc>>,
+    pub sunset: Option<DateTime<Utc>>,
     pub date: NaiveDate,
+    #[serde(default)]
+    pub kind: DayKind,
+}
+
+/// How far from the requested day cached sun times may be and still stand
+/// in for it while the provider is unreachable. Sunrise moves by at most a
+/// few minutes a day outside the polar regions.
+const NEARBY_CACHE_DAYS: i64 = 7;
+
+/// How long to wait for an HTTP provider to answer
+const HTTP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// When the provider may next be asked, after the daemon's backoff put it
+/// aside. Until then only cached and offline times are used.
+static PROVIDER_RETRY_AT: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Leave the provider alone for `delay`, or with `None` ask it again
+pub fn retry_provider_in(delay: Option<std::time::Duration>) {
+    *PROVIDER_RETRY_AT
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = delay.map(|delay| Instant::now() + delay);
+}
+
+/// How much longer the provider is being left alone, if at all
+pub fn provider_retry_in() -> Option<std::time::Duration> {
+    PROVIDER_RETRY_AT
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .and_then(|at| at.checked_duration_since(Instant::now()))
+        .filter(|left| !left.is_zero())
+}
+
+fn http_get(url: &str) -> reqwest::Result<String> {
+    reqwest::blocking::Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        .build()?
+        .get(url)
+        .send()?
+        .text()
+}
+
+/// Where a day's switching times came from, from best to last resort
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SunSource {
+    /// The configured provider, fresh or from the cache for that day
+    Provider(&'static str),
+    /// The provider's cached times for a nearby day, moved to this one
+    CachedNearby {
+        provider: &'static str,
+        date: NaiveDate,
+    },
+    /// The local calculation, standing in for a provider that failed
+    Offline,
+    /// No sun times at all; the day switches at fixed clock times
+    Fixed,
+}
+
+impl SunSource {
+    /// Whether the preferred source failed and the provider should be
+    /// tried again
+    pub fn is_fallback(&self) -> bool {
+        !matches!(self, SunSource::Provider(_))
+    }
+}
+
+impl std::fmt::Display for SunSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SunSource::Provider(name) => write!(f, "{}", name),
+            SunSource::CachedNearby { provider, date } => {
+                write!(f, "{} times cached for {}", provider, date)
+            }
+            SunSource::Offline => write!(f, "offline calculation"),
+            SunSource::Fixed => write!(f, "fixed schedule"),
+        }
+    }
+}
+
+/// A source of sunrise and sunset times for a location and date
+pub trait SunTimesProvider {
+    /// Short identifier used for caching and messages
+    fn name(&self) -> &'static str;
+
+    /// Whether results are worth caching on disk (i.e. they are expensive
+    /// or need the network)
+    fn cacheable(&self) -> bool {
+        true
+    }
+
+    /// How many days past today the provider has times for, if it's
+    /// limited
+    fn days_ahead_limit(&self) -> Option<u32> {
+        None
+    }
+
+    fn sun_times(&self, latitude: f64, longitude: f64, date: NaiveDate) -> Result<SunTimes>;
+
+    /// Sun times for each of `dates`, in order, each of which can fail on
+    /// its own. Providers that can answer a range in one request should
+    /// override this.
+    fn sun_times_for(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        dates: &[NaiveDate],
+    ) -> Result<Vec<Result<SunTimes>>> {
+        Ok(dates
+            .iter()
+            .map(|date| self.sun_times(latitude, longitude, *date))
+            .collect())
+    }
+}
+
+/// Build the provider selected in the config
+pub fn provider_from_config(config: &ProviderConfig) -> Result<Box<dyn SunTimesProvider>> {
+    let provider: Box<dyn SunTimesProvider> = match config.kind {
+        ProviderKind::Local => Box::new(LocalProvider),
+        ProviderKind::SunriseSunset => Box::new(SunriseSunsetProvider {
+            base_url: config
+                .url
+                .clone()
+                .unwrap_or_else(|| SUNRISE_SUNSET_URL.to_string()),
+        }),
+        ProviderKind::OpenMeteo => Box::new(OpenMeteoProvider {
+            base_url: config
+                .url
+                .clone()
+                .unwrap_or_else(|| OPEN_METEO_URL.to_string()),
+        }),
+        ProviderKind::Fixture => Box::new(FixtureProvider {
+            path: config
+                .path
+                .clone()
+                .context("The fixture provider requires 'path' in [provider]")?,
+        }),
+    };
+    Ok(provider)
+}
+
+/// Computes sun times offline using the NOAA algorithm
+pub struct LocalProvider;
+
+impl SunTimesProvider for LocalProvider {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn cacheable(&self) -> bool {
+        false
+    }
+
+    fn sun_times(&self, latitude: f64, longitude: f64, date: NaiveDate) -> Result<SunTimes> {
+        Ok(SunTimes::compute(latitude, longitude, date))
+    }
+}
+
+const SUNRISE_SUNSET_URL: &str = "https://api.sunrise-sunset.org";
+
+/// Fetches sun times from the sunrise-sunset.org API
+pub struct SunriseSunsetProvider {
+    base_url: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -75,109 +256,366 @@ struct ApiResults {
     sunset: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct CachedData {
-    sun_times: SunTimes,
-    cached_at: DateTime<Utc>,
+impl SunriseSunsetProvider {
+    fn parse(body: &str, latitude: f64, longitude: f64, date: NaiveDate) -> Result<SunTimes> {
+        let response: ApiResponse =
+            serde_json::from_str(body).with_context(|| "Failed to parse API response")?;
+
+        if response.status != "OK" {
+            anyhow::bail!("API returned error status: {}", response.status);
+        }
+
+        // During polar day and night the API reports the Unix epoch for
+        // both times, so fall back to the local calculation to classify it
+        let results = response.results;
+        if results.sunrise.timestamp() <= 1 || results.sunset.timestamp() <= 1 {
+            return Ok(SunTimes::compute(latitude, longitude, date));
+        }
+
+        Ok(SunTimes {
+            sunrise: Some(results.sunrise),
+            sunset: Some(results.sunset),
+            date,
+            kind: DayKind::Normal,
+        })
+    }
 }
 
-impl SunTimes {
-    pub fn fetch(latitude: f64, longitude: f64) -> Result<Self> {
+impl SunTimesProvider for SunriseSunsetProvider {
+    fn name(&self) -> &'static str {
+        "sunrise-sunset"
+    }
+
+    fn sun_times(&self, latitude: f64, longitude: f64, date: NaiveDate) -> Result<SunTimes> {
         let url = format!(
-            "https://api.sunrise-sunset.org/json?lat={}&lng={}&formatted=0",
-            latitude, longitude
+            "{}/json?lat={}&lng={}&date={}&formatted=0",
+            self.base_url.trim_end_matches('/'),
+            latitude,
+            longitude,
+            date.format("%Y-%m-%d")
         );
 
-        let response: ApiResponse = reqwest::blocking::get(&url)
-            .with_context(|| "Failed to fetch sun times from API")?
-            .json()
-            .with_context(|| "Failed to parse API response")?;
+        let body = http_get(&url).with_context(|| "Failed to fetch sun times from API")?;
 
-        if response.status != "OK" {
-            anyhow::bail!("API returned error status: {}", response.status);
+        Self::parse(&body, latitude, longitude, date)
+    }
+}
+
+const OPEN_METEO_URL: &str = "https://api.open-meteo.com";
+
+/// Fetches sun times from an Open-Meteo compatible forecast API
+pub struct OpenMeteoProvider {
+    base_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenMeteoResponse {
+    daily: OpenMeteoDaily,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenMeteoDaily {
+    time: Vec<NaiveDate>,
+    sunrise: Vec<Option<String>>,
+    sunset: Vec<Option<String>>,
+}
+
+impl OpenMeteoProvider {
+    fn parse(body: &str, latitude: f64, longitude: f64, date: NaiveDate) -> Result<SunTimes> {
+        let response: OpenMeteoResponse =
+            serde_json::from_str(body).with_context(|| "Failed to parse Open-Meteo response")?;
+        let daily = response.daily;
+
+        let index = daily
+            .time
+            .iter()
+            .position(|d| *d == date)
+            .with_context(|| format!("Open-Meteo response has no entry for {}", date))?;
+
+        // Times are requested in GMT and come back without an offset
+        let parse_time = |s: &str| -> Result<DateTime<Utc>> {
+            let naive = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M")
+                .with_context(|| format!("Invalid time in Open-Meteo response: {}", s))?;
+            Ok(naive.and_utc())
+        };
+
+        let sunrise = daily.sunrise.get(index).context("Missing sunrise")?;
+        let sunset = daily.sunset.get(index).context("Missing sunset")?;
+
+        match (sunrise, sunset) {
+            (Some(sunrise), Some(sunset)) => Ok(SunTimes {
+                sunrise: Some(parse_time(sunrise)?),
+                sunset: Some(parse_time(sunset)?),
+                date,
+                kind: DayKind::Normal,
+            }),
+            // No sunrise or sunset means polar day or night, which the
+            // local calculation tells apart
+            _ => Ok(SunTimes::compute(latitude, longitude, date)),
         }
+    }
+}
 
-        let today = Local::now().date_naive();
+impl SunTimesProvider for OpenMeteoProvider {
+    fn name(&self) -> &'static str {
+        "open-meteo"
+    }
 
-        Ok(SunTimes {
-            sunrise: response.results.sunrise,
-            sunset: response.results.sunset,
-            date: today,
-        })
+    /// The forecast API covers today and the next 15 days
+    fn days_ahead_limit(&self) -> Option<u32> {
+        Some(15)
     }
 
-    pub fn get_cached_or_fetch(latitude: f64, longitude: f64) -> Result<Self> {
-        let cache_path = Config::cache_dir()?.join("sun_times.json");
-        let today = Local::now().date_naive();
+    fn sun_times(&self, latitude: f64, longitude: f64, date: NaiveDate) -> Result<SunTimes> {
+        self.sun_times_for(latitude, longitude, &[date])?.remove(0)
+    }
 
-        // Try to load from cache
-        if cache_path.exists() {
-            if let Ok(content) = fs::read_to_string(&cache_path) {
-                if let Ok(cached) = serde_json::from_str::<CachedData>(&content) {
-                    if cached.sun_times.date == today {
-                        return Ok(cached.sun_times);
-                    }
-                }
+    fn sun_times_for(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        dates: &[NaiveDate],
+    ) -> Result<Vec<Result<SunTimes>>> {
+        let (Some(start), Some(end)) = (dates.iter().min(), dates.iter().max()) else {
+            return Ok(Vec::new());
+        };
+
+        // Fetch the whole range in one request
+        let url = format!(
+            "{}/v1/forecast?latitude={}&longitude={}&daily=sunrise,sunset&timezone=GMT&start_date={}&end_date={}",
+            self.base_url.trim_end_matches('/'),
+            latitude,
+            longitude,
+            start.format("%Y-%m-%d"),
+            end.format("%Y-%m-%d")
+        );
+
+        let body = http_get(&url).with_context(|| "Failed to fetch sun times from Open-Meteo")?;
+
+        Ok(dates
+            .iter()
+            .map(|date| Self::parse(&body, latitude, longitude, *date))
+            .collect())
+    }
+}
+
+/// Reads sun times from a JSON file containing a list of
+/// `{ "date", "sunrise", "sunset" }` entries
+pub struct FixtureProvider {
+    path: PathBuf,
+}
+
+impl FixtureProvider {
+    fn parse(content: &str, date: NaiveDate) -> Result<SunTimes> {
+        let entries: Vec<SunTimes> =
+            serde_json::from_str(content).with_context(|| "Failed to parse fixture file")?;
+
+        entries
+            .into_iter()
+            .find(|t| t.date == date)
+            .with_context(|| format!("Fixture has no sun times for {}", date))
+    }
+}
+
+impl SunTimesProvider for FixtureProvider {
+    fn name(&self) -> &'static str {
+        "fixture"
+    }
+
+    fn cacheable(&self) -> bool {
+        false
+    }
+
+    fn sun_times(&self, _latitude: f64, _longitude: f64, date: NaiveDate) -> Result<SunTimes> {
+        let content = fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read fixture file {:?}", self.path))?;
+        Self::parse(&content, date)
+    }
+}
+
+impl SunTimes {
+    /// Compute sunrise and sunset locally for the given date, without any
+    /// network access.
+    pub fn compute(latitude: f64, longitude: f64, date: NaiveDate) -> Self {
+        let sunrise = solar::crossing(date, latitude, longitude, solar::SUNRISE_ELEVATION, true);
+        let sunset = solar::crossing(date, latitude, longitude, solar::SUNRISE_ELEVATION, false);
+
+        let (sunrise, sunset, kind) = match (sunrise, sunset) {
+            (Crossing::At(sunrise), Crossing::At(sunset)) => {
+                (Some(sunrise), Some(sunset), DayKind::Normal)
+            }
+            (Crossing::AlwaysAbove, _) | (_, Crossing::AlwaysAbove) => {
+                (None, None, DayKind::PolarDay)
             }
+            _ => (None, None, DayKind::PolarNight),
+        };
+
+        SunTimes {
+            sunrise,
+            sunset,
+            date,
+            kind,
         }
+    }
 
-        // Fetch fresh data
-        let sun_times = Self::fetch(latitude, longitude)?;
+    pub fn get_cached_or_fetch(config: &Config, date: NaiveDate) -> Result<Self> {
+        let provider = provider_from_config(&config.provider)?;
+        let location = config.require_location()?;
+        let (latitude, longitude) = (location.latitude, location.longitude);
+
+        let resting = || match provider_retry_in() {
+            Some(left) => Err(anyhow::anyhow!(
+                "Not asking {} again for another {}s",
+                provider.name(),
+                left.as_secs()
+            )),
+            None => Ok(()),
+        };
 
-        // Cache the result
-        let cache_dir = Config::cache_dir()?;
-        fs::create_dir_all(&cache_dir)?;
+        if !provider.cacheable() {
+            resting()?;
+            return provider.sun_times(latitude, longitude, date);
+        }
 
-        let cached = CachedData {
-            sun_times: sun_times.clone(),
-            cached_at: Utc::now(),
-        };
+        let mut cache = SunCache::load();
+        if let Some(cached) = cache.get(provider.name(), latitude, longitude, date) {
+            return Ok(cached.clone());
+        }
 
-        let content = serde_json::to_string_pretty(&cached)?;
-        fs::write(&cache_path, content)?;
+        // Fetch fresh data and cache the result
+        resting()?;
+        let sun_times = provider.sun_times(latitude, longitude, date)?;
+        cache.insert(provider.name(), latitude, longitude, sun_times.clone());
+        cache.prune(Local::now().date_naive());
+        cache.save()?;
 
         Ok(sun_times)
     }
 
-    pub fn sunrise_local(&self) -> DateTime<Local> {
-        self.sunrise.with_timezone(&Local)
-    }
+    /// Like `get_cached_or_fetch`, but when the provider fails, fall back
+    /// to its cached times for a nearby day and then to the local
+    /// calculation, so there is always an answer given a location
+    pub fn get_with_fallback(config: &Config, date: NaiveDate) -> Result<(Self, SunSource)> {
+        let location = config.require_location()?;
+        let (latitude, longitude) = (location.latitude, location.longitude);
+
+        let provider = match provider_from_config(&config.provider) {
+            Ok(provider) => provider,
+            Err(e) => {
+                log::warn!("{:#}", e);
+                return Ok((Self::compute(latitude, longitude, date), SunSource::Offline));
+            }
+        };
+        match Self::get_cached_or_fetch(config, date) {
+            Ok(sun_times) => return Ok((sun_times, SunSource::Provider(provider.name()))),
+            // Already reported when the provider failed
+            Err(e) if provider_retry_in().is_some() => log::debug!("{:#}", e),
+            Err(e) => log::warn!(
+                "Failed to get sun times for {} from {}: {:#}",
+                date,
+                provider.name(),
+                e
+            ),
+        }
 
-    pub fn sunset_local(&self) -> DateTime<Local> {
-        self.sunset.with_timezone(&Local)
+        let cache = SunCache::load();
+        if let Some(cached) = cache.nearest(
+            provider.name(),
+            latitude,
+            longitude,
+            date,
+            NEARBY_CACHE_DAYS,
+        ) {
+            let source = SunSource::CachedNearby {
+                provider: provider.name(),
+                date: cached.date,
+            };
+            return Ok((cached.shifted_to(date), source));
+        }
+        Ok((Self::compute(latitude, longitude, date), SunSource::Offline))
     }
 
-    pub fn is_daytime(&self) -> bool {
-        let now = Utc::now();
-        now >= self.sunrise && now < self.sunset
+    /// These times moved by whole days to stand in for `date`
+    pub fn shifted_to(&self, date: NaiveDate) -> Self {
+        let days = date - self.date;
+        SunTimes {
+            sunrise: self.sunrise.map(|t| t + days),
+            sunset: self.sunset.map(|t| t + days),
+            date,
+            kind: self.kind,
+        }
     }
 
-    pub fn current_mode(&self) -> ThemeMode {
-        if self.is_daytime() {
-            ThemeMode::Light
-        } else {
-            ThemeMode::Dark
+    /// Make sure the cache holds sun times for `today` and the configured
+    /// number of days ahead, or as far as the provider goes. Returns how
+    /// many days were fetched.
+    pub fn prefetch(config: &Config, today: NaiveDate) -> Result<usize> {
+        let provider = provider_from_config(&config.provider)?;
+        if !provider.cacheable() || !config.schedule.uses_sun() || provider_retry_in().is_some() {
+            return Ok(0);
+        }
+
+        let location = config.require_location()?;
+        let (latitude, longitude) = (location.latitude, location.longitude);
+        let days_ahead = provider
+            .days_ahead_limit()
+            .map_or(config.cache.days_ahead, |limit| {
+                limit.min(config.cache.days_ahead)
+            });
+        let mut cache = SunCache::load();
+        let missing: Vec<NaiveDate> = (0..=days_ahead as i64)
+            .map(|offset| today + Duration::days(offset))
+            .filter(|date| {
+                cache
+                    .get(provider.name(), latitude, longitude, *date)
+                    .is_none()
+            })
+            .collect();
+
+        if missing.is_empty() {
+            return Ok(0);
         }
+
+        // Keep the days that came back even if others failed
+        let mut fetched = 0;
+        let results = provider.sun_times_for(latitude, longitude, &missing)?;
+        for (date, sun_times) in missing.iter().zip(results) {
+            match sun_times {
+                Ok(sun_times) => {
+                    cache.insert(provider.name(), latitude, longitude, sun_times);
+                    fetched += 1;
+                }
+                Err(e) => log::warn!("Failed to prefetch sun times for {}: {:#}", date, e),
+            }
+        }
+        if fetched > 0 {
+            cache.prune(today);
+            cache.save()?;
+        }
+
+        Ok(fetched)
     }
 
-    pub fn next_switch(&self) -> (DateTime<Local>, ThemeMode) {
-        let now = Utc::now();
-        if now < self.sunrise {
-            (self.sunrise_local(), ThemeMode::Light)
-        } else if now < self.sunset {
-            (self.sunset_local(), ThemeMode::Dark)
-        } else {
-            // After sunset, next switch is tomorrow's sunrise
-            // For simplicity, we'll just indicate it's after today's events
-            (
-                self.sunrise_local() + chrono::Duration::days(1),
-                ThemeMode::Light,
-            )
+    pub fn sunrise_local(&self) -> Option<DateTime<Local>> {
+        self.sunrise.map(|t| t.with_timezone(&Local))
+    }
+
+    pub fn sunset_local(&self) -> Option<DateTime<Local>> {
+        self.sunset.map(|t| t.with_timezone(&Local))
+    }
+
+    /// Explanation of a polar day or night, for display
+    pub fn polar_description(&self) -> Option<&'static str> {
+        match self.kind {
+            DayKind::Normal => None,
+            DayKind::PolarDay => Some("Polar day: the sun does not set today"),
+            DayKind::PolarNight => Some("Polar night: the sun does not rise today"),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ThemeMode {
     Light,
     Dark,
@@ -252,4 +690,157 @@ mod tests {
         assert!("invalid".parse::<ThemeMode>().is_err());
         assert!("".parse::<ThemeMode>().is_err());
     }
+
+    #[test]
+    fn test_compute_sun_times() {
+        let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let times = SunTimes::compute(51.5074, -0.1278, date);
+        assert_eq!(times.date, date);
+        assert_eq!(times.kind, DayKind::Normal);
+        assert!(times.sunrise.unwrap() < times.sunset.unwrap());
+    }
+
+    #[test]
+    fn test_compute_tromso_june_is_polar_day() {
+        let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let times = SunTimes::compute(69.6492, 18.9553, date);
+        assert_eq!(times.kind, DayKind::PolarDay);
+        assert!(times.sunrise.is_none());
+        assert!(times.sunset.is_none());
+    }
+
+    #[test]
+    fn test_compute_tromso_december_is_polar_night() {
+        let date = NaiveDate::from_ymd_opt(2024, 12, 21).unwrap();
+        let times = SunTimes::compute(69.6492, 18.9553, date);
+        assert_eq!(times.kind, DayKind::PolarNight);
+        assert!(times.sunrise.is_none());
+        assert!(times.sunset.is_none());
+    }
+
+    #[test]
+    fn test_parse_sunrise_sunset_response() {
+        let body = r#"{"results":{"sunrise":"2024-06-21T03:43:09+00:00","sunset":"2024-06-21T20:21:41+00:00","day_length":59912},"status":"OK"}"#;
+        let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let times = SunriseSunsetProvider::parse(body, 51.5074, -0.1278, date).unwrap();
+        assert_eq!(
+            times.sunrise.unwrap().to_rfc3339(),
+            "2024-06-21T03:43:09+00:00"
+        );
+        assert_eq!(
+            times.sunset.unwrap().to_rfc3339(),
+            "2024-06-21T20:21:41+00:00"
+        );
+        assert_eq!(times.date, date);
+    }
+
+    #[test]
+    fn test_parse_sunrise_sunset_polar_epoch() {
+        let body = r#"{"results":{"sunrise":"1970-01-01T00:00:01+00:00","sunset":"1970-01-01T00:00:01+00:00","day_length":0},"status":"OK"}"#;
+        let june = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let times = SunriseSunsetProvider::parse(body, 69.6492, 18.9553, june).unwrap();
+        assert_eq!(times.kind, DayKind::PolarDay);
+
+        let december = NaiveDate::from_ymd_opt(2024, 12, 21).unwrap();
+        let times = SunriseSunsetProvider::parse(body, 69.6492, 18.9553, december).unwrap();
+        assert_eq!(times.kind, DayKind::PolarNight);
+    }
+
+    #[test]
+    fn test_parse_sunrise_sunset_error_status() {
+        let body = r#"{"results":{"sunrise":"2024-06-21T03:43:09+00:00","sunset":"2024-06-21T20:21:41+00:00"},"status":"INVALID_DATE"}"#;
+        let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        assert!(SunriseSunsetProvider::parse(body, 51.5074, -0.1278, date).is_err());
+    }
+
+    #[test]
+    fn test_parse_open_meteo_response() {
+        let body = r#"{"latitude":51.5,"longitude":-0.12,"daily":{"time":["2024-06-21","2024-06-22"],"sunrise":["2024-06-21T03:43","2024-06-22T03:43"],"sunset":["2024-06-21T20:21","2024-06-22T20:22"]}}"#;
+        let date = NaiveDate::from_ymd_opt(2024, 6, 22).unwrap();
+        let times = OpenMeteoProvider::parse(body, 51.5074, -0.1278, date).unwrap();
+        assert_eq!(
+            times.sunrise.unwrap().to_rfc3339(),
+            "2024-06-22T03:43:00+00:00"
+        );
+        assert_eq!(
+            times.sunset.unwrap().to_rfc3339(),
+            "2024-06-22T20:22:00+00:00"
+        );
+
+        let missing = NaiveDate::from_ymd_opt(2024, 6, 23).unwrap();
+        assert!(OpenMeteoProvider::parse(body, 51.5074, -0.1278, missing).is_err());
+    }
+
+    #[test]
+    fn test_parse_open_meteo_polar() {
+        let body = r#"{"daily":{"time":["2024-12-21"],"sunrise":[null],"sunset":[null]}}"#;
+        let date = NaiveDate::from_ymd_opt(2024, 12, 21).unwrap();
+        let times = OpenMeteoProvider::parse(body, 69.6492, 18.9553, date).unwrap();
+        assert_eq!(times.kind, DayKind::PolarNight);
+    }
+
+    #[test]
+    fn test_parse_fixture() {
+        let content = r#"[
+            {"date":"2024-06-21","sunrise":"2024-06-21T03:43:00Z","sunset":"2024-06-21T20:21:00Z"},
+            {"date":"2024-06-22","sunrise":"2024-06-22T03:44:00Z","sunset":"2024-06-22T20:21:00Z"}
+        ]"#;
+        let date = NaiveDate::from_ymd_opt(2024, 6, 22).unwrap();
+        let times = FixtureProvider::parse(content, date).unwrap();
+        assert_eq!(
+            times.sunrise.unwrap().to_rfc3339(),
+            "2024-06-22T03:44:00+00:00"
+        );
+        assert_eq!(times.kind, DayKind::Normal);
+
+        let missing = NaiveDate::from_ymd_opt(2024, 6, 23).unwrap();
+        assert!(FixtureProvider::parse(content, missing).is_err());
+    }
+
+    #[test]
+    fn test_shifted_to() {
+        let date = NaiveDate::from_ymd_opt(2024, 6, 10).unwrap();
+        let shifted =
+            SunTimes::compute(51.5074, -0.1278, date - Duration::days(2)).shifted_to(date);
+        let actual = SunTimes::compute(51.5074, -0.1278, date);
+        assert_eq!(shifted.date, date);
+        let drift = shifted.sunrise.unwrap() - actual.sunrise.unwrap();
+        assert!(drift.num_minutes().abs() < 5);
+    }
+
+    #[test]
+    fn test_fallback_when_provider_fails() {
+        let mut config = Config {
+            location: Some(crate::config::Location {
+                latitude: 51.5074,
+                longitude: -0.1278,
+            }),
+            ..Default::default()
+        };
+        config.provider.kind = ProviderKind::Fixture;
+        let date = NaiveDate::from_ymd_opt(2024, 6, 10).unwrap();
+
+        // The fixture provider has no path, so it can't even be built
+        let (sun_times, source) = SunTimes::get_with_fallback(&config, date).unwrap();
+        assert_eq!(source, SunSource::Offline);
+        assert_eq!(sun_times.date, date);
+
+        config.provider.kind = ProviderKind::Local;
+        let (_, source) = SunTimes::get_with_fallback(&config, date).unwrap();
+        assert_eq!(source, SunSource::Provider("local"));
+        assert!(!source.is_fallback());
+    }
+
+    #[test]
+    fn test_provider_from_config() {
+        let config = ProviderConfig::default();
+        assert_eq!(provider_from_config(&config).unwrap().name(), "local");
+
+        let config = ProviderConfig {
+            kind: ProviderKind::Fixture,
+            url: None,
+            path: None,
+        };
+        assert!(provider_from_config(&config).is_err());
+    }
 }
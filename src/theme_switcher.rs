@@ -3,30 +3,103 @@ use std::fs;
 use std::path::PathBuf;
 
 use crate::config::Config;
+use crate::fade::Fade;
+use crate::palette::Palette;
 use crate::sun_times::ThemeMode;
 
 pub struct ThemeSwitcher {
     config: Config,
 }
 
+/// Which targets one apply reached, and why the others failed
+#[derive(Debug, Default)]
+pub struct Applied {
+    pub succeeded: Vec<&'static str>,
+    pub failed: Vec<(&'static str, anyhow::Error)>,
+}
+
+impl Applied {
+    fn record(&mut self, target: &'static str, result: Result<()>) {
+        match result {
+            Ok(()) => self.succeeded.push(target),
+            Err(e) => self.failed.push((target, e)),
+        }
+    }
+
+    /// The failures as one message, if there were any
+    pub fn error(&self) -> Option<String> {
+        if self.failed.is_empty() {
+            return None;
+        }
+        let errors: Vec<String> = self
+            .failed
+            .iter()
+            .map(|(target, e)| format!("{}: {:#}", target, e))
+            .collect();
+        Some(errors.join("; "))
+    }
+}
+
 impl ThemeSwitcher {
     pub fn new(config: Config) -> Self {
         Self { config }
     }
 
     pub fn apply_theme(&self, mode: ThemeMode) -> Result<()> {
-        self.apply_ghostty_theme(mode)?;
-        self.apply_neovim_theme(mode)?;
-        self.write_state(mode)?;
-        Ok(())
+        self.apply(mode, None)
     }
 
-    fn apply_ghostty_theme(&self, mode: ThemeMode) -> Result<()> {
-        let theme_name = match mode {
-            ThemeMode::Light => &self.config.themes.ghostty.light,
-            ThemeMode::Dark => &self.config.themes.ghostty.dark,
-        };
+    /// Apply the themes for `phase`, falling back to those for `mode` for
+    /// targets without one for the phase
+    pub fn apply(&self, mode: ThemeMode, phase: Option<&str>) -> Result<()> {
+        match self.apply_targets(mode, phase).error() {
+            Some(error) => Err(anyhow::anyhow!(error)),
+            None => Ok(()),
+        }
+    }
 
+    /// Like `apply`, but carry on past a failing target and report how
+    /// each one went
+    pub fn apply_targets(&self, mode: ThemeMode, phase: Option<&str>) -> Applied {
+        let mut applied = Applied::default();
+        applied.record("ghostty", self.apply_ghostty_theme(mode, phase));
+        applied.record(
+            "neovim",
+            self.apply_neovim_theme(mode, phase)
+                .and_then(|()| self.write_state(mode)),
+        );
+        applied
+    }
+
+    /// Show the current step of `fade` in Ghostty, generated from the
+    /// palettes either side of the switch. Neovim follows `mode` as usual.
+    pub fn apply_fade(&self, mode: ThemeMode, phase: Option<&str>, fade: &Fade) -> Applied {
+        let mut applied = Applied::default();
+        applied.record("ghostty", self.fade_ghostty_theme(fade));
+        applied.record(
+            "neovim",
+            self.apply_neovim_theme(mode, phase)
+                .and_then(|()| self.write_state(mode)),
+        );
+        applied
+    }
+
+    fn fade_ghostty_theme(&self, fade: &Fade) -> Result<()> {
+        let themes = &self.config.themes.ghostty;
+        let from = Palette::load(themes.theme_for(fade.from, fade.from_phase.as_deref()))?;
+        let to = Palette::load(themes.theme_for(fade.to, fade.to_phase.as_deref()))?;
+
+        let path = from
+            .mix(&to, fade.fraction())
+            .write(&format!("suntheme-fade-{:02}", fade.step))?;
+        self.set_ghostty_theme(&path.to_string_lossy())
+    }
+
+    fn apply_ghostty_theme(&self, mode: ThemeMode, phase: Option<&str>) -> Result<()> {
+        self.set_ghostty_theme(self.config.themes.ghostty.theme_for(mode, phase))
+    }
+
+    fn set_ghostty_theme(&self, theme_name: &str) -> Result<()> {
         let ghostty_config = Self::ghostty_config_path()?;
 
         if !ghostty_config.exists() {
@@ -123,23 +196,23 @@ impl ThemeSwitcher {
         Ok(config_dir.join("ghostty").join("config"))
     }
 
-    fn apply_neovim_theme(&self, mode: ThemeMode) -> Result<()> {
-        let theme_name = match mode {
-            ThemeMode::Light => &self.config.themes.neovim.light,
-            ThemeMode::Dark => &self.config.themes.neovim.dark,
-        };
+    fn apply_neovim_theme(&self, mode: ThemeMode, phase: Option<&str>) -> Result<()> {
+        let theme_name = self.config.themes.neovim.theme_for(mode, phase);
 
         let state_file = Config::state_file()?;
         let state_dir = state_file.parent().unwrap();
         fs::create_dir_all(state_dir)?;
 
         // Write theme info as simple key=value for easy parsing
-        let content = format!(
+        let mut content = format!(
             "mode={}\ntheme={}\nbackground={}\n",
             mode.as_str(),
             theme_name,
             mode.as_str()
         );
+        if let Some(phase) = phase {
+            content.push_str(&format!("phase={}\n", phase));
+        }
 
         fs::write(&state_file, content)
             .with_context(|| format!("Failed to write state file at {:?}", state_file))?;
@@ -204,4 +277,19 @@ mod tests {
         let result = ThemeSwitcher::update_ghostty_theme(content, "new-theme");
         assert!(result.contains("theme = new-theme"));
     }
+
+    #[test]
+    fn test_theme_pair_phase_themes() {
+        use crate::config::ThemePair;
+
+        let pair: ThemePair =
+            toml::from_str("light = \"day\"\ndark = \"night\"\ndusk = \"moon\"\n").unwrap();
+        assert_eq!(pair.theme_for(ThemeMode::Dark, Some("dusk")), "moon");
+        assert_eq!(pair.theme_for(ThemeMode::Light, Some("dawn")), "day");
+        assert_eq!(pair.theme_for(ThemeMode::Dark, None), "night");
+
+        let plain: ThemePair = toml::from_str("light = \"day\"\ndark = \"night\"\n").unwrap();
+        assert!(plain.phases.is_empty());
+        assert!(!toml::to_string(&plain).unwrap().contains("phases"));
+    }
 }
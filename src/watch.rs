@@ -0,0 +1,205 @@
+use std::fs;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How often the config file is checked where inotify isn't available
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Notices when the config file is written, replaced or removed. On Linux
+/// the daemon polls an inotify descriptor on the config directory, which
+/// also catches editors that save by renaming a new file over the old.
+/// Elsewhere, or if inotify fails, the file's modification time is
+/// compared every few seconds.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    #[cfg(target_os = "linux")]
+    inotify: Option<inotify::Inotify>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            modified: modified(&path),
+            #[cfg(target_os = "linux")]
+            inotify: inotify::Inotify::watch(&path)
+                .map_err(|e| log::warn!("Failed to watch config, polling instead: {}", e))
+                .ok(),
+            path,
+        }
+    }
+
+    /// The descriptor to poll for changes, if there is one
+    pub fn fd(&self) -> Option<RawFd> {
+        #[cfg(target_os = "linux")]
+        if let Some(inotify) = &self.inotify {
+            return Some(inotify.fd());
+        }
+        None
+    }
+
+    /// How long the daemon may sleep before calling `changed` again, when
+    /// there's no descriptor to wake it
+    pub fn poll_interval(&self) -> Option<Duration> {
+        match self.fd() {
+            Some(_) => None,
+            None => Some(POLL_INTERVAL),
+        }
+    }
+
+    /// Whether the config file changed since the last call
+    pub fn changed(&mut self) -> bool {
+        #[cfg(target_os = "linux")]
+        if let Some(inotify) = &self.inotify {
+            return inotify.changed(&self.path);
+        }
+
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use std::path::Path;
+
+    /// Size of `struct inotify_event` before its name
+    const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+
+    pub struct Inotify {
+        fd: OwnedFd,
+    }
+
+    impl Inotify {
+        /// Watch the directory holding `path` for files being written,
+        /// moved in or out, created or deleted
+        pub fn watch(path: &Path) -> io::Result<Self> {
+            let dir = path.parent().ok_or(io::ErrorKind::NotFound)?;
+            let dir = CString::new(dir.as_os_str().as_bytes())?;
+
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+            let mask = libc::IN_CLOSE_WRITE
+                | libc::IN_MOVED_TO
+                | libc::IN_MOVED_FROM
+                | libc::IN_CREATE
+                | libc::IN_DELETE;
+            if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), mask) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { fd })
+        }
+
+        pub fn fd(&self) -> RawFd {
+            self.fd.as_raw_fd()
+        }
+
+        /// Read every pending event, returning whether any was for `path`.
+        /// The directory also holds the daemon's state files, whose events
+        /// are skipped.
+        pub fn changed(&self, path: &Path) -> bool {
+            let name = path.file_name().map(|name| name.as_bytes());
+            let mut changed = false;
+            let mut buf = [0u8; 4096];
+            loop {
+                let len = unsafe {
+                    libc::read(
+                        self.fd.as_raw_fd(),
+                        buf.as_mut_ptr() as *mut libc::c_void,
+                        buf.len(),
+                    )
+                };
+                if len <= 0 {
+                    break;
+                }
+                changed |= event_names(&buf[..len as usize]).any(|n| Some(n) == name);
+            }
+            changed
+        }
+    }
+
+    /// The file names in a buffer of inotify events
+    fn event_names(mut buf: &[u8]) -> impl Iterator<Item = &[u8]> {
+        std::iter::from_fn(move || {
+            if buf.len() < HEADER {
+                return None;
+            }
+            let event: libc::inotify_event =
+                unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const _) };
+            let end = (HEADER + event.len as usize).min(buf.len());
+            let name = &buf[HEADER..end];
+            buf = &buf[end..];
+            // Names are padded with NULs to keep events aligned
+            let len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+            Some(&name[..len])
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn event(name: &str, padded: usize) -> Vec<u8> {
+            let mut bytes = vec![0u8; HEADER];
+            bytes[12..16].copy_from_slice(&(padded as u32).to_ne_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.resize(HEADER + padded, 0);
+            bytes
+        }
+
+        #[test]
+        fn test_event_names() {
+            let mut buf = event("override.json", 16);
+            buf.extend(event("config.toml", 16));
+            buf.extend(event("", 0));
+            let names: Vec<&[u8]> = event_names(&buf).collect();
+            assert_eq!(names, [&b"override.json"[..], b"config.toml", b""]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notices_config_changes() {
+        let dir = std::env::temp_dir().join(format!("suntheme-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(&path, "[cache]\n").unwrap();
+
+        let mut watcher = ConfigWatcher::new(path.clone());
+        assert!(!watcher.changed());
+
+        // Other files in the directory don't count
+        fs::write(dir.join("override.json"), "{}").unwrap();
+        assert!(!watcher.changed());
+
+        // Saved the way editors do, by renaming a new file over the old
+        std::thread::sleep(Duration::from_millis(20));
+        fs::write(dir.join("config.toml.tmp"), "[cache]\ndays = 3\n").unwrap();
+        fs::rename(dir.join("config.toml.tmp"), &path).unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
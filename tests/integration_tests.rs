@@ -1,4 +1,5 @@
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 fn suntheme_bin() -> PathBuf {
@@ -6,6 +7,61 @@ fn suntheme_bin() -> PathBuf {
     PathBuf::from(env!("CARGO_BIN_EXE_suntheme"))
 }
 
+/// Create an isolated home directory so tests never touch the real config
+fn test_home(name: &str) -> PathBuf {
+    let home = std::env::temp_dir().join(format!("suntheme-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&home);
+    fs::create_dir_all(&home).unwrap();
+    home
+}
+
+fn config_dir(home: &Path) -> PathBuf {
+    if cfg!(target_os = "macos") {
+        home.join("Library/Application Support/suntheme")
+    } else {
+        home.join(".config/suntheme")
+    }
+}
+
+fn write_config(home: &Path, extra: &str) {
+    let dir = config_dir(home);
+    fs::create_dir_all(&dir).unwrap();
+    let config = format!(
+        "[location]\nlatitude = 51.5074\nlongitude = -0.1278\n\n\
+         [themes.ghostty]\nlight = \"day\"\ndark = \"night\"\n\n\
+         [themes.neovim]\nlight = \"day\"\ndark = \"night\"\n\n{}",
+        extra
+    );
+    fs::write(dir.join("config.toml"), config).unwrap();
+}
+
+fn suntheme_in(home: &Path) -> Command {
+    let mut cmd = Command::new(suntheme_bin());
+    cmd.env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("XDG_CACHE_HOME", home.join(".cache"))
+        .env("XDG_DATA_HOME", home.join(".local/share"))
+        .env("TZ", "UTC");
+    cmd
+}
+
+/// Wait until the daemon running in `home` answers `status` over its
+/// control socket. By then its signal handlers are in place and it has
+/// applied a theme, so signals and requests behave as they would later.
+fn wait_for_daemon(home: &Path) {
+    let socket = config_dir(home).join("daemon.sock");
+    for _ in 0..200 {
+        if socket.exists() {
+            let status = suntheme_in(home).arg("status").output().unwrap();
+            if String::from_utf8_lossy(&status.stdout).contains("running (PID:") {
+                return;
+            }
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    panic!("The daemon didn't start listening on {:?}", socket);
+}
+
 #[test]
 fn test_help_command() {
     let output = Command::new(suntheme_bin())
@@ -69,3 +125,751 @@ fn test_invalid_command() {
 
     assert!(!output.status.success());
 }
+
+#[test]
+fn test_sun_command_with_fixture_provider() {
+    let home = test_home("fixture");
+    let today = chrono::Utc::now().date_naive();
+    let fixture = home.join("sun.json");
+    fs::write(
+        &fixture,
+        format!(
+            r#"[{{"date":"{d}","sunrise":"{d}T06:15:00Z","sunset":"{d}T19:45:00Z"}}]"#,
+            d = today.format("%Y-%m-%d")
+        ),
+    )
+    .unwrap();
+    write_config(
+        &home,
+        &format!("[provider]\nkind = \"fixture\"\npath = {:?}\n", fixture),
+    );
+
+    let output = suntheme_in(&home)
+        .arg("sun")
+        .output()
+        .expect("Failed to run suntheme");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.contains("Sunrise: 06:15:00"));
+    assert!(stdout.contains("Sunset:  19:45:00"));
+}
+
+#[test]
+fn test_cache_show_and_clear() {
+    let home = test_home("cache");
+    write_config(&home, "");
+
+    let output = suntheme_in(&home)
+        .args(["cache", "show"])
+        .output()
+        .expect("Failed to run suntheme");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("empty"));
+
+    let output = suntheme_in(&home)
+        .args(["cache", "clear"])
+        .output()
+        .expect("Failed to run suntheme");
+    assert!(output.status.success());
+}
+
+/// Answer Open-Meteo requests from a local thread with made-up sun times
+/// for each day asked for, except `missing`. Returns the base URL.
+fn serve_open_meteo(missing: chrono::NaiveDate) -> String {
+    use std::io::{BufRead, BufReader, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = String::new();
+            BufReader::new(&stream).read_line(&mut request).unwrap();
+            let param = |name: &str| {
+                let start = request.find(&format!("{}=", name)).unwrap() + name.len() + 1;
+                chrono::NaiveDate::parse_from_str(&request[start..start + 10], "%Y-%m-%d").unwrap()
+            };
+
+            let days: Vec<_> = param("start_date")
+                .iter_days()
+                .take_while(|day| *day <= param("end_date"))
+                .filter(|day| *day != missing)
+                .collect();
+            let times = |time: &str| {
+                days.iter()
+                    .map(|day| format!("\"{}T{}\"", day, time))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            };
+            let body = format!(
+                r#"{{"daily":{{"time":[{}],"sunrise":[{}],"sunset":[{}]}}}}"#,
+                days.iter()
+                    .map(|day| format!("\"{}\"", day))
+                    .collect::<Vec<_>>()
+                    .join(","),
+                times("05:00"),
+                times("20:00")
+            );
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+        }
+    });
+    url
+}
+
+#[test]
+fn test_daemon_prefetches_into_cache() {
+    let home = test_home("prefetch");
+    let today = chrono::Utc::now().date_naive();
+    let day = |offset| today + chrono::Duration::days(offset);
+    let url = serve_open_meteo(day(2));
+    write_config(
+        &home,
+        &format!(
+            "[provider]\nkind = \"open-meteo\"\nurl = {:?}\n\n[cache]\ndays_ahead = 3\n",
+            url
+        ),
+    );
+
+    let child = suntheme_in(&home)
+        .args(["run", "--foreground"])
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    wait_for_daemon(&home);
+    Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Prefetched sun times for 3 days"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains(&format!("Failed to prefetch sun times for {}", day(2))));
+
+    // The day the provider left out doesn't stop the others being cached
+    let output = suntheme_in(&home)
+        .args(["cache", "show"])
+        .output()
+        .expect("Failed to run suntheme");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for offset in [0, 1, 3] {
+        let line = stdout
+            .lines()
+            .find(|line| line.starts_with(&day(offset).to_string()))
+            .unwrap_or_else(|| panic!("{} isn't cached: {}", day(offset), stdout));
+        assert!(line.contains("05:00:00 - 20:00:00 open-meteo"), "{}", line);
+    }
+    assert!(!stdout.contains(&day(2).to_string()));
+
+    let _ = fs::remove_dir_all(&home);
+}
+
+#[test]
+fn test_sun_command_with_fixed_schedule() {
+    let home = test_home("fixed");
+    let dir = config_dir(&home);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("config.toml"),
+        "[themes.ghostty]\nlight = \"day\"\ndark = \"night\"\n\n\
+         [themes.neovim]\nlight = \"day\"\ndark = \"night\"\n\n\
+         [schedule]\nmode = \"fixed\"\n\n[schedule.fixed]\nlight = \"08:00\"\ndark = \"18:00\"\n",
+    )
+    .unwrap();
+
+    let output = suntheme_in(&home)
+        .arg("sun")
+        .output()
+        .expect("Failed to run suntheme");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.contains("Light:   08:00:00 (fixed)"));
+    assert!(stdout.contains("Dark:    18:00:00 (fixed)"));
+    assert!(!stdout.contains("Sunrise"));
+}
+
+#[test]
+fn test_schedule_command_shows_week() {
+    let home = test_home("schedule");
+    write_config(
+        &home,
+        "[schedule]\nmode = \"fixed\"\n\n[schedule.fixed]\nlight = \"08:00\"\ndark = \"18:00\"\n\n\
+         [[schedule.rules]]\ndays = [\"sun\"]\nmode = \"dark\"\n",
+    );
+
+    let output = suntheme_in(&home)
+        .arg("schedule")
+        .output()
+        .expect("Failed to run suntheme");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let days: Vec<&str> = stdout.lines().filter(|l| l.contains(" 20")).collect();
+    assert_eq!(days.len(), 7);
+    assert_eq!(
+        days.iter()
+            .filter(|l| l.contains("fixed  light 08:00  dark 18:00"))
+            .count(),
+        6
+    );
+    assert!(days
+        .iter()
+        .any(|l| l.starts_with("Sun") && l.contains("dark   dark all day")));
+}
+
+#[test]
+fn test_except_add_list_remove() {
+    let home = test_home("except");
+    write_config(&home, "");
+
+    let output = suntheme_in(&home)
+        .args([
+            "except",
+            "add",
+            "light",
+            "--start",
+            "2099-06-10",
+            "--end",
+            "2099-06-14",
+            "--note",
+            "conference",
+        ])
+        .output()
+        .expect("Failed to run suntheme");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let config = fs::read_to_string(config_dir(&home).join("config.toml")).unwrap();
+    assert!(config.contains("[[exceptions]]"));
+    assert!(config.contains("2099-06-15 00:00"));
+
+    let output = suntheme_in(&home)
+        .args(["except", "list"])
+        .output()
+        .expect("Failed to run suntheme");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("1. 2099-06-10 00:00 - 2099-06-15 00:00  light conference"));
+
+    let output = suntheme_in(&home)
+        .args(["except", "remove", "1"])
+        .output()
+        .expect("Failed to run suntheme");
+    assert!(output.status.success());
+
+    let config = fs::read_to_string(config_dir(&home).join("config.toml")).unwrap();
+    assert!(!config.contains("exceptions"));
+}
+
+#[test]
+fn test_set_with_duration_creates_override() {
+    let home = test_home("override");
+    write_config(&home, "");
+
+    let output = suntheme_in(&home)
+        .args(["set", "dark", "--for", "2h"])
+        .output()
+        .expect("Failed to run suntheme");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(config_dir(&home).join("override.json").exists());
+
+    let output = suntheme_in(&home)
+        .arg("status")
+        .output()
+        .expect("Failed to run suntheme");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Override: dark until"));
+
+    let output = suntheme_in(&home)
+        .args(["set", "light", "--for", "2h", "--until", "17:00"])
+        .output()
+        .expect("Failed to run suntheme");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_history_records_switches() {
+    let home = test_home("history");
+    write_config(&home, "");
+
+    let output = suntheme_in(&home)
+        .arg("history")
+        .output()
+        .expect("Failed to run suntheme");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No theme switches"));
+
+    for mode in ["dark", "light"] {
+        let output = suntheme_in(&home)
+            .args(["set", mode, "--for", "1h"])
+            .output()
+            .expect("Failed to run suntheme");
+        assert!(output.status.success());
+    }
+
+    let output = suntheme_in(&home)
+        .args(["history", "--days", "1"])
+        .output()
+        .expect("Failed to run suntheme");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().filter(|l| l.contains("manual")).collect();
+    assert_eq!(lines.len(), 2, "{}", stdout);
+    assert!(lines[0].contains("dark") && lines[0].contains("ghostty, neovim"));
+    assert!(lines[1].contains("light"));
+
+    // Further back than time goes shows everything
+    let output = suntheme_in(&home)
+        .args(["history", "--days", "4294967295"])
+        .output()
+        .expect("Failed to run suntheme");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout)
+            .matches("manual")
+            .count(),
+        2
+    );
+
+    let output = suntheme_in(&home)
+        .args(["history", "--json"])
+        .output()
+        .expect("Failed to run suntheme");
+    let entries: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(entries[1]["mode"], "light");
+    assert_eq!(entries[1]["trigger"], "manual");
+    assert_eq!(entries[1]["targets"][0], "ghostty");
+
+    let _ = fs::remove_dir_all(&home);
+}
+
+#[test]
+fn test_pause_and_resume() {
+    let home = test_home("pause");
+    write_config(&home, "");
+
+    let output = suntheme_in(&home)
+        .args(["pause", "--for", "30m"])
+        .output()
+        .expect("Failed to run suntheme");
+    assert!(output.status.success());
+    assert!(config_dir(&home).join("pause.json").exists());
+
+    let output = suntheme_in(&home)
+        .arg("status")
+        .output()
+        .expect("Failed to run suntheme");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Paused:  until"));
+
+    let output = suntheme_in(&home)
+        .arg("resume")
+        .output()
+        .expect("Failed to run suntheme");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("resumed"));
+    assert!(!config_dir(&home).join("pause.json").exists());
+}
+
+#[test]
+fn test_run_foreground_stops_on_sigterm() {
+    let home = test_home("foreground");
+    write_config(
+        &home,
+        "[schedule]\nmode = \"fixed\"\n\n[schedule.fixed]\nlight = \"07:00\"\ndark = \"19:00\"\n",
+    );
+
+    let child = suntheme_in(&home)
+        .args(["run", "--foreground"])
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    wait_for_daemon(&home);
+    let killed = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Current mode"));
+    assert!(stderr.contains("Stopping"));
+    assert!(!config_dir(&home).join("daemon.pid").exists());
+
+    let _ = fs::remove_dir_all(&home);
+}
+
+#[test]
+fn test_restart_leaves_a_foreground_daemon_alone() {
+    let home = test_home("restart-foreground");
+    write_config(
+        &home,
+        "[schedule]\nmode = \"fixed\"\n\n[schedule.fixed]\nlight = \"07:00\"\ndark = \"19:00\"\n",
+    );
+
+    let mut child = suntheme_in(&home)
+        .args(["run", "--foreground"])
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    wait_for_daemon(&home);
+
+    let output = suntheme_in(&home).arg("restart").output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("run --foreground"), "{}", stderr);
+
+    // Still the same daemon, and no detached copy beside it
+    let status = suntheme_in(&home).arg("status").output().unwrap();
+    assert!(
+        String::from_utf8_lossy(&status.stdout).contains(&format!("running (PID: {})", child.id()))
+    );
+    assert!(!config_dir(&home).join("daemon.pid").exists());
+
+    Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    child.wait().unwrap();
+    let _ = fs::remove_dir_all(&home);
+}
+
+#[test]
+fn test_daemon_falls_back_when_provider_fails() {
+    let home = test_home("provider-down");
+    let missing = home.join("missing.json");
+    write_config(
+        &home,
+        &format!("[provider]\nkind = \"fixture\"\npath = {:?}\n", missing),
+    );
+
+    let output = suntheme_in(&home)
+        .arg("sun")
+        .output()
+        .expect("Failed to run suntheme");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Source:  offline calculation"));
+
+    let child = suntheme_in(&home)
+        .args(["run", "--foreground"])
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    wait_for_daemon(&home);
+    Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // Once is enough: later lookups leave the provider alone until the retry
+    assert_eq!(
+        stderr.matches("Failed to get sun times").count(),
+        1,
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("Schedule from offline calculation, retrying in"));
+    assert!(stderr.contains("Current mode"));
+
+    let _ = fs::remove_dir_all(&home);
+}
+
+#[test]
+fn test_commands_go_through_control_socket() {
+    let home = test_home("control");
+    write_config(
+        &home,
+        "[schedule]\nmode = \"fixed\"\n\n[schedule.fixed]\nlight = \"07:00\"\ndark = \"19:00\"\n",
+    );
+
+    let mut child = suntheme_in(&home)
+        .args(["run", "--foreground"])
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    wait_for_daemon(&home);
+
+    // No PID file in the foreground, so only the socket can find the daemon
+    let status = suntheme_in(&home).arg("status").output().unwrap();
+    let stdout = String::from_utf8_lossy(&status.stdout);
+    assert!(stdout.contains(&format!("running (PID: {})", child.id())));
+
+    let set = suntheme_in(&home)
+        .args(["set", "light", "--for", "1h"])
+        .output()
+        .unwrap();
+    assert!(set.status.success());
+    assert!(config_dir(&home).join("override.json").exists());
+
+    let bad = suntheme_in(&home)
+        .args(["set", "light", "--until", "bogus"])
+        .output()
+        .unwrap();
+    assert!(!bad.status.success());
+    assert!(String::from_utf8_lossy(&bad.stderr).contains("Invalid time"));
+
+    let _ = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status();
+    child.wait().unwrap();
+    assert!(!config_dir(&home).join("daemon.sock").exists());
+
+    let _ = fs::remove_dir_all(&home);
+}
+
+#[test]
+fn test_bad_control_requests_get_errors() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    let home = test_home("control-errors");
+    write_config(
+        &home,
+        "[schedule]\nmode = \"fixed\"\n\n[schedule.fixed]\nlight = \"07:00\"\ndark = \"19:00\"\n",
+    );
+
+    let mut child = suntheme_in(&home)
+        .args(["run", "--foreground"])
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    wait_for_daemon(&home);
+
+    let ask = |request: &str| -> serde_json::Value {
+        let mut stream = UnixStream::connect(config_dir(&home).join("daemon.sock")).unwrap();
+        writeln!(stream, "{}", request).unwrap();
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply).unwrap();
+        serde_json::from_str(&reply).unwrap()
+    };
+
+    for request in [
+        r#"{"command":"pause","duration":"999999999999999d"}"#,
+        r#"{"command":"set-mode","mode":"dark","duration":"200000000d"}"#,
+        r#"{"command":"toggle","duration":"99999999999999999999m"}"#,
+    ] {
+        let reply = ask(request);
+        assert_eq!(reply["result"], "error", "{}", reply);
+        assert!(reply["message"]
+            .as_str()
+            .unwrap()
+            .contains("Invalid duration"));
+    }
+    assert_eq!(ask(r#"{"command":"get-status"}"#)["result"], "status");
+    assert!(child.try_wait().unwrap().is_none());
+
+    let _ = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status();
+    child.wait().unwrap();
+    let _ = fs::remove_dir_all(&home);
+}
+
+#[test]
+fn test_set_applies_while_paused() {
+    let home = test_home("paused-set");
+    write_config(
+        &home,
+        "[schedule]\nmode = \"fixed\"\n\n[schedule.fixed]\nlight = \"07:00\"\ndark = \"19:00\"\n",
+    );
+
+    let mut child = suntheme_in(&home)
+        .args(["run", "--foreground"])
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    wait_for_daemon(&home);
+
+    let pause = suntheme_in(&home).arg("pause").output().unwrap();
+    assert!(pause.status.success());
+    for mode in ["dark", "light"] {
+        let set = suntheme_in(&home)
+            .args(["set", mode, "--for", "1h"])
+            .output()
+            .unwrap();
+        assert!(set.status.success());
+        let state = fs::read_to_string(config_dir(&home).join("current_theme")).unwrap();
+        assert!(state.contains(&format!("mode={}\n", mode)), "{}", state);
+    }
+
+    let _ = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status();
+    child.wait().unwrap();
+    let _ = fs::remove_dir_all(&home);
+}
+
+#[test]
+fn test_daemon_reloads_changed_config() {
+    let home = test_home("reload");
+    let fixed =
+        "[schedule]\nmode = \"fixed\"\n\n[schedule.fixed]\nlight = \"07:00\"\ndark = \"19:00\"\n";
+    write_config(&home, fixed);
+
+    let child = suntheme_in(&home)
+        .args(["run", "--foreground"])
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    wait_for_daemon(&home);
+
+    // A fixed schedule without times is refused
+    write_config(&home, "[schedule]\nmode = \"fixed\"\n");
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    let reload = suntheme_in(&home).arg("reload").output().unwrap();
+    assert!(!reload.status.success());
+    assert!(String::from_utf8_lossy(&reload.stderr).contains("Fixed schedule needs"));
+
+    write_config(&home, fixed);
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    let _ = Command::new("kill")
+        .args(["-HUP", &child.id().to_string()])
+        .status();
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    let _ = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status();
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Keeping the old config"));
+    assert_eq!(stderr.matches("Reloaded config").count(), 2);
+
+    let _ = fs::remove_dir_all(&home);
+}
+
+#[test]
+fn test_stop_leaves_unrelated_process_alone() {
+    let home = test_home("stale-pid");
+    write_config(&home, "");
+
+    // A PID file naming a process that isn't suntheme, as after PID reuse
+    let mut other = Command::new("sleep").arg("30").spawn().unwrap();
+    fs::write(config_dir(&home).join("daemon.pid"), other.id().to_string()).unwrap();
+
+    let output = suntheme_in(&home).arg("stop").output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("stale PID file"));
+    assert!(other.try_wait().unwrap().is_none());
+    assert!(!config_dir(&home).join("daemon.pid").exists());
+
+    let _ = other.kill();
+    let _ = other.wait();
+    let _ = fs::remove_dir_all(&home);
+}
+
+#[test]
+fn test_one_daemon_at_a_time() {
+    let home = test_home("lock");
+    write_config(
+        &home,
+        "[schedule]\nmode = \"fixed\"\n\n[schedule.fixed]\nlight = \"07:00\"\ndark = \"19:00\"\n",
+    );
+
+    let mut child = suntheme_in(&home)
+        .args(["run", "--foreground"])
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    wait_for_daemon(&home);
+
+    let second = suntheme_in(&home)
+        .args(["run", "--foreground"])
+        .output()
+        .unwrap();
+    assert!(!second.status.success());
+    assert!(String::from_utf8_lossy(&second.stderr)
+        .contains(&format!("already running (PID: {})", child.id())));
+
+    // The lock names the foreground daemon, which has no PID file
+    let stop = suntheme_in(&home).arg("stop").output().unwrap();
+    assert!(stop.status.success());
+    assert!(String::from_utf8_lossy(&stop.stdout).contains("Daemon stopped"));
+    assert!(child.wait().unwrap().success());
+
+    let _ = fs::remove_dir_all(&home);
+}
+
+#[test]
+fn test_logs_since() {
+    let home = test_home("logs");
+    write_config(&home, "");
+    let now = chrono::Utc::now();
+    let old = now - chrono::Duration::hours(3);
+    let recent = now - chrono::Duration::minutes(5);
+    let dir = config_dir(&home);
+    fs::write(
+        dir.join("daemon.log.1"),
+        format!("[{}] INFO  Started\n", old.format("%Y-%m-%d %H:%M:%S")),
+    )
+    .unwrap();
+    fs::write(
+        dir.join("daemon.log"),
+        format!(
+            "[{}] INFO  Current mode: light\n\
+             {{\"time\":\"{}\",\"level\":\"warn\",\"target\":\"suntheme\",\"message\":\"Failed to prefetch\"}}\n\
+             thread 'main' panicked\n",
+            old.format("%Y-%m-%d %H:%M:%S"),
+            recent.to_rfc3339()
+        ),
+    )
+    .unwrap();
+
+    let output = suntheme_in(&home)
+        .args(["logs", "--since", "1h"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("Started"));
+    assert!(!stdout.contains("Current mode"));
+    assert!(stdout.contains("Failed to prefetch"));
+    assert!(stdout.contains("panicked"));
+
+    let output = suntheme_in(&home)
+        .args(["logs", "-n", "1"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "thread 'main' panicked\n"
+    );
+
+    let _ = fs::remove_dir_all(&home);
+}